@@ -0,0 +1,76 @@
+// Measures how the time to tokenize grows with the size of the input, on
+// generated C source of 1 to 8 MB. The lexer is linear in the input when
+// the time per MB stays about the same as the input doubles, and the bench
+// fails if it grows by more than MAX_RATIO.
+//
+//   cargo bench --bench tokenize
+use std::time::{Duration, Instant};
+
+use mmcc2::error::Source;
+use mmcc2::tokenize::tokenize;
+
+// Generates about size bytes of C, with a mix of keywords, identifiers,
+// numbers, strings, comments and operators like that of real code.
+fn generate(size: usize) -> String {
+    let mut text = String::with_capacity(size + 1024);
+    let mut i = 0;
+    while text.len() < size {
+        text.push_str(&format!(
+            "/* function {i} */\n\
+             static long f{i}(int a, char *s) {{\n\
+             \x20   long x = 0x{i:x} + a * {i}UL; // accumulate\n\
+             \x20   for (int j = 0; j < a && s[j] != '\\0'; j++) {{\n\
+             \x20       x += s[j] << 2 | (x >= 1.5e3 ? -j : j);\n\
+             \x20   }}\n\
+             \x20   return x == 0 ? \"empty\\n\"[0] : x;\n\
+             }}\n",
+            i = i
+        ));
+        i += 1;
+    }
+    text
+}
+
+// The time per MB may grow by this much from the smallest input to the
+// largest before the growth is reported as not linear.
+const MAX_RATIO: f64 = 2.0;
+
+fn main() {
+    let sizes = [1, 2, 4, 8];
+    let mut per_mb = Vec::new();
+    for mb in sizes {
+        let src = Source::new("bench.c".to_string(), generate(mb << 20));
+        // the fastest of a few runs, which is the least disturbed by noise
+        let mut best = None;
+        let mut count = 0;
+        for _ in 0..3 {
+            let start = Instant::now();
+            let tokens = tokenize(&src, 0).unwrap();
+            let elapsed = start.elapsed();
+            count = tokens.len();
+            best = Some(best.map_or(elapsed, |best: Duration| best.min(elapsed)));
+        }
+        let elapsed = best.unwrap();
+        println!(
+            "{} MB: {} tokens in {:.2?} ({:.2?} per MB)",
+            mb,
+            count,
+            elapsed,
+            elapsed / mb as u32
+        );
+        per_mb.push(elapsed.as_secs_f64() / mb as f64);
+    }
+
+    let ratio = per_mb[per_mb.len() - 1] / per_mb[0];
+    println!(
+        "time per MB at {} MB is {:.2}x that at {} MB (at most {:.1}x)",
+        sizes[sizes.len() - 1],
+        ratio,
+        sizes[0],
+        MAX_RATIO
+    );
+    if ratio > MAX_RATIO {
+        eprintln!("error: tokenizing does not scale linearly with the input");
+        std::process::exit(1);
+    }
+}
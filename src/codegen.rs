@@ -1,296 +1,1140 @@
-use std::process;
+use std::fmt::Write;
 
-use crate::parse::{Node, NodeKind, Parser};
-use crate::types::TypeKind;
+use crate::error::{CompileError, ErrorKind};
+use crate::parse::{Global, Node, NodeKind, Parser};
+use crate::tokenize::Span;
+use crate::types::{align_to, Type, TypeKind};
 
+// Appends a line of assembly to the generator's output.
+macro_rules! emit {
+    ($gen:expr, $($arg:tt)*) => {
+        writeln!($gen.out, $($arg)*).unwrap()
+    };
+}
+
+#[derive(Default)]
 pub struct Generator {
+    out: String,
     label: u32,
     var_offsets: Vec<usize>,
+    depth: usize,           // number of 8-byte values pushed since the prologue
+    breaks: Vec<String>,    // where break jumps to, innermost loop or switch last
+    continues: Vec<String>, // where continue jumps to, innermost loop last
+    switches: Vec<u32>,     // labels of the enclosing switches, innermost last
+    func_name: String,      // function being generated, to make its goto labels unique
 }
 
+static ARG_REGS1: [&str; 6] = ["dil", "sil", "dl", "cl", "r8b", "r9b"];
+static ARG_REGS2: [&str; 6] = ["di", "si", "dx", "cx", "r8w", "r9w"];
 static ARG_REGS4: [&str; 6] = ["edi", "esi", "edx", "ecx", "r8d", "r9d"];
 static ARG_REGS8: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
 
+// float and double arguments are passed in xmm0 to xmm7
+const FP_ARG_REGS: usize = 8;
+
+// Suffix of the SSE instructions that operate on a float or double.
+fn sse_suffix(ty: &Type) -> &'static str {
+    if ty.kind == TypeKind::TyFloat {
+        "ss"
+    } else {
+        "sd"
+    }
+}
+
+// Where an argument is passed: in the nth general-purpose or vector
+// register, or on the stack.
+#[derive(PartialEq)]
+enum ArgLoc {
+    Gp(usize),
+    Fp(usize),
+    Stack,
+}
+
+// Assigns each argument or parameter of the given types a place as the
+// SysV ABI does: integers and pointers go in the next general-purpose
+// register, floats and doubles in the next vector register, and the rest on
+// the stack.
+fn classify_args<'a>(tys: impl Iterator<Item = &'a Type>) -> Vec<ArgLoc> {
+    let (mut gp, mut fp) = (0, 0);
+    tys.map(|ty| {
+        if ty.is_flonum() && fp < FP_ARG_REGS {
+            fp += 1;
+            ArgLoc::Fp(fp - 1)
+        } else if !ty.is_flonum() && gp < ARG_REGS8.len() {
+            gp += 1;
+            ArgLoc::Gp(gp - 1)
+        } else {
+            ArgLoc::Stack
+        }
+    })
+    .collect()
+}
+
+// Counts the arguments passed in general-purpose registers, vector
+// registers and on the stack.
+fn count_args(locs: &[ArgLoc]) -> (usize, usize, usize) {
+    let count = |f: fn(&ArgLoc) -> bool| locs.iter().filter(|loc| f(loc)).count();
+    (
+        count(|loc| matches!(loc, ArgLoc::Gp(_))),
+        count(|loc| matches!(loc, ArgLoc::Fp(_))),
+        count(|loc| *loc == ArgLoc::Stack),
+    )
+}
+
 impl Generator {
+    fn push(&mut self, operand: &str) {
+        emit!(self, "  push {}", operand);
+        self.depth += 1;
+    }
+
+    fn pop(&mut self, reg: &str) {
+        emit!(self, "  pop {}", reg);
+        self.depth -= 1;
+    }
+
     fn new_label(&mut self) -> u32 {
         let label = self.label;
         self.label += 1;
         label
     }
 
-    fn gen_lval(&mut self, node: Box<Node>) {
-        if node.kind == NodeKind::NdLv {
-            println!("  mov rax, rbp");
-            println!("  sub rax, {}", self.var_offsets[node.lvar.unwrap().id]);
-            println!("  push rax");
+    // Generates the body of a loop, in which break jumps to .L.end.label
+    // and continue to the given label.
+    fn gen_loop_body(
+        &mut self,
+        body: Box<Node>,
+        label: u32,
+        cont: String,
+    ) -> Result<(), CompileError> {
+        self.breaks.push(format!(".L.end.{}", label));
+        self.continues.push(cont);
+        self.gen(body)?;
+        self.continues.pop();
+        self.breaks.pop();
+        Ok(())
+    }
+
+    // Jumps to the case of the switch whose value is in rax through a table
+    // of offsets, indexed by the value minus the smallest case. Values out
+    // of its range, or in a gap between cases, go to default.
+    fn gen_jump_table(&mut self, cases: &[i64], label: u32, default: &str) {
+        let min = *cases.iter().min().unwrap();
+        let max = *cases.iter().max().unwrap();
+
+        emit!(self, "  mov rdi, {}", min);
+        emit!(self, "  sub rax, rdi");
+        emit!(self, "  cmp rax, {}", max - min);
+        emit!(self, "  ja {}", default);
+        emit!(self, "  lea rdi, [rip+.L.table.{}]", label);
+        emit!(self, "  movsxd rax, dword ptr [rdi+rax*4]");
+        emit!(self, "  add rax, rdi");
+        emit!(self, "  jmp rax");
+
+        emit!(self, ".section .rodata");
+        emit!(self, "  .align 4");
+        emit!(self, ".L.table.{}:", label);
+        for val in min..=max {
+            match cases.iter().position(|&case| case == val) {
+                Some(i) => emit!(self, "  .long .L.case.{}.{} - .L.table.{}", label, i, label),
+                None => emit!(self, "  .long {} - .L.table.{}", default, label),
+            }
+        }
+        emit!(self, ".text");
+    }
+
+    // Replaces the address on top of the stack with the value it points to.
+    fn load(&mut self, ty: &Type) {
+        if ty.kind == TypeKind::TyArr || ty.is_struct() {
+            // an array is not loaded, so that it decays to a pointer to its first
+            // element, and a struct is handled through its address
             return;
         }
-        if node.kind == NodeKind::NdDeref {
-            self.gen(node.lhs.unwrap());
+
+        self.pop("rax");
+        match (ty.size, ty.is_unsigned || ty.kind == TypeKind::TyBool) {
+            (1, false) => emit!(self, "  movsx rax, byte ptr [rax]"),
+            (1, true) => emit!(self, "  movzx eax, byte ptr [rax]"),
+            (2, false) => emit!(self, "  movsx rax, word ptr [rax]"),
+            (2, true) => emit!(self, "  movzx eax, word ptr [rax]"),
+            (4, false) => emit!(self, "  movsxd rax, dword ptr [rax]"),
+            (4, true) => emit!(self, "  mov eax, dword ptr [rax]"),
+            _ => emit!(self, "  mov rax, [rax]"),
+        }
+        self.push("rax");
+    }
+
+    // Converts the value in rax from one type to another. A value narrower
+    // than 64 bits only has its low 32 bits used, except that it is widened
+    // to 64 bits on conversion to a 64-bit type.
+    fn cast(&mut self, from: &Type, to: &Type) {
+        if to.kind == TypeKind::TyVoid {
+            return;
+        }
+        if from.is_flonum() || to.is_flonum() {
+            self.cast_flonum(from, to);
+            return;
+        }
+        if to.kind == TypeKind::TyBool {
+            emit!(
+                self,
+                "  cmp {}, 0",
+                if from.size == 8 { "rax" } else { "eax" }
+            );
+            emit!(self, "  setne al");
+            emit!(self, "  movzx eax, al");
             return;
         }
 
-        eprintln!("The left value of the assignment is not a variable.");
-        process::exit(1);
+        match (to.size, to.is_unsigned) {
+            (1, false) => emit!(self, "  movsx eax, al"),
+            (1, true) => emit!(self, "  movzx eax, al"),
+            (2, false) => emit!(self, "  movsx eax, ax"),
+            (2, true) => emit!(self, "  movzx eax, ax"),
+            // the upper half of rax is cleared, so that it can be compared
+            // as a whole
+            (4, false) if from.size == 8 => emit!(self, "  movsxd rax, eax"),
+            (4, true) if from.size == 8 => emit!(self, "  mov eax, eax"),
+            (8, _) if from.size < 8 && from.is_unsigned => emit!(self, "  mov eax, eax"),
+            (8, _) if from.size < 8 => emit!(self, "  movsxd rax, eax"),
+            _ => {}
+        }
     }
 
-    fn gen(&mut self, node: Box<Node>) {
+    // Applies a binary operator to the floats or doubles in rax and rdi, and
+    // pushes the result.
+    fn gen_flonum_binary(
+        &mut self,
+        kind: NodeKind,
+        ty: &Type,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        let sfx = sse_suffix(ty);
+        emit!(self, "  movq xmm0, rax");
+        emit!(self, "  movq xmm1, rdi");
+
+        match kind {
+            NodeKind::NdAdd => emit!(self, "  add{} xmm0, xmm1", sfx),
+            NodeKind::NdSub => emit!(self, "  sub{} xmm0, xmm1", sfx),
+            NodeKind::NdMul => emit!(self, "  mul{} xmm0, xmm1", sfx),
+            NodeKind::NdDiv => emit!(self, "  div{} xmm0, xmm1", sfx),
+            // a comparison with NaN is false, except for !=; unordered
+            // operands set the parity flag, and also ZF and CF, so < and
+            // <= are tested as > and >= with the operands swapped
+            NodeKind::NdEq | NodeKind::NdNe => {
+                emit!(self, "  ucomi{} xmm0, xmm1", sfx);
+                if kind == NodeKind::NdEq {
+                    emit!(self, "  sete al");
+                    emit!(self, "  setnp dl");
+                    emit!(self, "  and al, dl");
+                } else {
+                    emit!(self, "  setne al");
+                    emit!(self, "  setp dl");
+                    emit!(self, "  or al, dl");
+                }
+            }
+            NodeKind::NdMt | NodeKind::NdOm => {
+                emit!(self, "  ucomi{} xmm0, xmm1", sfx);
+                emit!(
+                    self,
+                    "  {} al",
+                    if kind == NodeKind::NdMt {
+                        "seta"
+                    } else {
+                        "setae"
+                    }
+                );
+            }
+            NodeKind::NdLt | NodeKind::NdOl => {
+                emit!(self, "  ucomi{} xmm1, xmm0", sfx);
+                emit!(
+                    self,
+                    "  {} al",
+                    if kind == NodeKind::NdLt {
+                        "seta"
+                    } else {
+                        "setae"
+                    }
+                );
+            }
+            _ => {
+                return Err(CompileError::new(
+                    ErrorKind::Internal,
+                    span,
+                    "unknown node".to_string(),
+                ));
+            }
+        }
+
+        match kind {
+            NodeKind::NdAdd | NodeKind::NdSub | NodeKind::NdMul | NodeKind::NdDiv => {
+                emit!(self, "  movq rax, xmm0");
+            }
+            _ => emit!(self, "  movzx eax, al"),
+        }
+        self.push("rax");
+
+        Ok(())
+    }
+
+    // Converts the value in rax to or from a float or double, which is kept
+    // in rax as its bits like any other value.
+    fn cast_flonum(&mut self, from: &Type, to: &Type) {
+        if from.kind == to.kind {
+            return;
+        }
+
+        if from.is_flonum() && to.kind == TypeKind::TyBool {
+            // NaN compares unordered, and is true
+            emit!(self, "  movq xmm0, rax");
+            emit!(self, "  xorps xmm1, xmm1");
+            emit!(self, "  ucomi{} xmm0, xmm1", sse_suffix(from));
+            emit!(self, "  setne al");
+            emit!(self, "  setp dl");
+            emit!(self, "  or al, dl");
+            emit!(self, "  movzx eax, al");
+            return;
+        }
+
+        if from.is_flonum() && to.is_flonum() {
+            emit!(self, "  movq xmm0, rax");
+            emit!(
+                self,
+                "  cvt{}2{} xmm0, xmm0",
+                sse_suffix(from),
+                sse_suffix(to)
+            );
+            emit!(self, "  movq rax, xmm0");
+            return;
+        }
+
+        if to.is_flonum() {
+            let sfx = sse_suffix(to);
+            if from.size == 8 && from.is_unsigned {
+                // cvtsi2sd takes a signed operand, so a value with the top
+                // bit set is halved, keeping its lowest bit for rounding, and
+                // the result doubled
+                let label = self.new_label();
+                emit!(self, "  test rax, rax");
+                emit!(self, "  js .L.cast.{}", label);
+                emit!(self, "  cvtsi2{} xmm0, rax", sfx);
+                emit!(self, "  jmp .L.cast_end.{}", label);
+                emit!(self, ".L.cast.{}:", label);
+                emit!(self, "  mov rdi, rax");
+                emit!(self, "  and eax, 1");
+                emit!(self, "  shr rdi");
+                emit!(self, "  or rdi, rax");
+                emit!(self, "  cvtsi2{} xmm0, rdi", sfx);
+                emit!(self, "  add{} xmm0, xmm0", sfx);
+                emit!(self, ".L.cast_end.{}:", label);
+            } else if from.size == 8 {
+                emit!(self, "  cvtsi2{} xmm0, rax", sfx);
+            } else if from.is_unsigned {
+                emit!(self, "  mov eax, eax");
+                emit!(self, "  cvtsi2{} xmm0, rax", sfx);
+            } else {
+                emit!(self, "  cvtsi2{} xmm0, eax", sfx);
+            }
+            emit!(self, "  movq rax, xmm0");
+            return;
+        }
+
+        // to an integer, truncating toward zero
+        emit!(self, "  movq xmm0, rax");
+        if to.size == 8 && to.is_unsigned {
+            // cvttsd2si yields a signed result, so a value of 2^63 or more
+            // is reduced by 2^63 before the conversion and the top bit set
+            // after it
+            let sfx = sse_suffix(from);
+            let bound: u64 = if from.kind == TypeKind::TyFloat {
+                0x5f00_0000
+            } else {
+                0x43e0_0000_0000_0000
+            };
+            let label = self.new_label();
+            emit!(self, "  mov rdi, {:#x}", bound);
+            emit!(self, "  movq xmm1, rdi");
+            emit!(self, "  ucomi{} xmm0, xmm1", sfx);
+            emit!(self, "  jae .L.cast.{}", label);
+            emit!(self, "  cvtt{}2si rax, xmm0", sfx);
+            emit!(self, "  jmp .L.cast_end.{}", label);
+            emit!(self, ".L.cast.{}:", label);
+            emit!(self, "  sub{} xmm0, xmm1", sfx);
+            emit!(self, "  cvtt{}2si rax, xmm0", sfx);
+            emit!(self, "  mov rdi, 0x8000000000000000");
+            emit!(self, "  xor rax, rdi");
+            emit!(self, ".L.cast_end.{}:", label);
+        } else if to.size == 8 || to.is_unsigned {
+            emit!(self, "  cvtt{}2si rax, xmm0", sse_suffix(from));
+        } else {
+            emit!(self, "  cvtt{}2si eax, xmm0", sse_suffix(from));
+        }
+        if to.size < 4 {
+            self.cast(&Type::new_int(), to);
+        }
+    }
+
+    // Evaluates a condition and sets the flags by comparing it with zero.
+    fn gen_cond(&mut self, node: Box<Node>) -> Result<(), CompileError> {
+        let ty = node.ty.clone().unwrap();
+        self.gen(node)?;
+        self.pop("rax");
+        if ty.is_flonum() {
+            emit!(self, "  movq xmm0, rax");
+            emit!(self, "  xorps xmm1, xmm1");
+            emit!(self, "  ucomi{} xmm0, xmm1", sse_suffix(&ty));
+        } else if ty.size == 8 {
+            emit!(self, "  cmp rax, 0");
+        } else {
+            emit!(self, "  cmp eax, 0");
+        }
+        Ok(())
+    }
+
+    // Stores the value on top of the stack to the address below it, and
+    // leaves the value on the stack.
+    fn store(&mut self, ty: &Type) {
+        self.pop("rdi");
+        self.pop("rax");
+
+        if ty.is_struct() {
+            // copy the struct that rdi points to
+            for i in 0..ty.size {
+                emit!(self, "  mov r8b, [rdi+{}]", i);
+                emit!(self, "  mov [rax+{}], r8b", i);
+            }
+            self.push("rdi");
+            return;
+        }
+
+        match ty.size {
+            1 => emit!(self, "  mov [rax], dil"),
+            2 => emit!(self, "  mov [rax], di"),
+            4 => emit!(self, "  mov [rax], edi"),
+            _ => emit!(self, "  mov [rax], rdi"),
+        }
+        self.push("rdi");
+    }
+
+    // `operand` names the use of the expression in the lvalue error, e.g.
+    // "unary '&' operand".
+    fn gen_lval(&mut self, node: Box<Node>, operand: &str) -> Result<(), CompileError> {
+        if node.kind == NodeKind::NdLv {
+            emit!(self, "  mov rax, rbp");
+            emit!(
+                self,
+                "  sub rax, {}",
+                self.var_offsets[node.lvar.unwrap().id]
+            );
+            self.push("rax");
+            return Ok(());
+        }
+        if node.kind == NodeKind::NdGv {
+            emit!(self, "  lea rax, [rip+{}]", node.gvar.unwrap().name);
+            self.push("rax");
+            return Ok(());
+        }
+        if node.kind == NodeKind::NdDeref {
+            return self.gen(node.lhs.unwrap());
+        }
+        if node.kind == NodeKind::NdMember {
+            let offset = node.member.unwrap().offset;
+            self.gen_lval(node.lhs.unwrap(), operand)?;
+            self.pop("rax");
+            emit!(self, "  add rax, {}", offset);
+            self.push("rax");
+            return Ok(());
+        }
+
+        Err(CompileError::new(
+            ErrorKind::NotLvalue,
+            node.span,
+            format!("lvalue required as {}", operand),
+        ))
+    }
+
+    fn gen(&mut self, node: Box<Node>) -> Result<(), CompileError> {
+        let span = node.span;
         match node.kind {
             NodeKind::NdRt => {
-                self.gen(node.lhs.unwrap());
-                println!("  pop rax");
-                println!("  mov rsp, rbp");
-                println!("  pop rbp");
-                println!("  ret");
-                return;
+                if let Some(lhs) = node.lhs {
+                    let is_flonum = lhs.ty.as_ref().unwrap().is_flonum();
+                    self.gen(lhs)?;
+                    self.pop("rax");
+                    if is_flonum {
+                        emit!(self, "  movq xmm0, rax");
+                    }
+                }
+                emit!(self, "  mov rsp, rbp");
+                emit!(self, "  pop rbp");
+                emit!(self, "  ret");
+                return Ok(());
             }
             NodeKind::NdBlock => {
                 for block in node.blocks {
-                    self.gen(Box::new(block));
+                    self.gen(Box::new(block))?;
                 }
-                return;
+                return Ok(());
+            }
+            NodeKind::NdExprStmt => {
+                self.gen(node.lhs.unwrap())?;
+                emit!(self, "  add rsp, 8");
+                self.depth -= 1;
+                return Ok(());
             }
             NodeKind::NdIf => {
                 let label = self.new_label();
-                self.gen(node.cond.unwrap());
-                println!("  pop rax");
-                println!("  cmp rax, 0");
+                self.gen_cond(node.cond.unwrap())?;
                 match node.els {
                     Some(els) => {
-                        println!("  je .L.else.{}", label);
-                        self.gen(node.then.unwrap());
-                        println!("  jmp .L.end.{}", label);
-                        println!(".L.else.{}:", label);
-                        self.gen(els);
-                        println!(".L.end.{}:", label);
+                        emit!(self, "  je .L.else.{}", label);
+                        self.gen(node.then.unwrap())?;
+                        emit!(self, "  jmp .L.end.{}", label);
+                        emit!(self, ".L.else.{}:", label);
+                        self.gen(els)?;
+                        emit!(self, ".L.end.{}:", label);
                     }
                     None => {
-                        println!("  je .L.end.{}", label);
-                        self.gen(node.then.unwrap());
-                        println!(".L.end.{}:", label);
+                        emit!(self, "  je .L.end.{}", label);
+                        self.gen(node.then.unwrap())?;
+                        emit!(self, ".L.end.{}:", label);
                     }
                 }
-                return;
+                return Ok(());
             }
             NodeKind::NdWhile => {
                 let label = self.new_label();
-                println!(".L.begin.{}:", label);
-                self.gen(node.cond.unwrap());
-                println!("  pop rax");
-                println!("  cmp rax, 0");
-                println!("  je .L.end.{}", label);
-                self.gen(node.then.unwrap());
-                println!("  jmp .L.begin.{}", label);
-                println!(".L.end.{}:", label);
-                return;
+                emit!(self, ".L.begin.{}:", label);
+                self.gen_cond(node.cond.unwrap())?;
+                emit!(self, "  je .L.end.{}", label);
+                self.gen_loop_body(node.then.unwrap(), label, format!(".L.begin.{}", label))?;
+                emit!(self, "  jmp .L.begin.{}", label);
+                emit!(self, ".L.end.{}:", label);
+                return Ok(());
+            }
+            NodeKind::NdDo => {
+                let label = self.new_label();
+                emit!(self, ".L.begin.{}:", label);
+                self.gen_loop_body(node.then.unwrap(), label, format!(".L.continue.{}", label))?;
+                emit!(self, ".L.continue.{}:", label);
+                self.gen_cond(node.cond.unwrap())?;
+                emit!(self, "  jne .L.begin.{}", label);
+                emit!(self, ".L.end.{}:", label);
+                return Ok(());
             }
             NodeKind::NdFor => {
                 let label = self.new_label();
                 if let Some(preop) = node.preop {
-                    self.gen(preop);
+                    self.gen(preop)?;
                 }
-                println!(".L.begin.{}:", label);
+                emit!(self, ".L.begin.{}:", label);
                 if let Some(cond) = node.cond {
-                    self.gen(cond);
-                    println!("  pop rax");
-                    println!("  cmp rax, 0");
-                    println!("  je .L.end.{}", label);
+                    self.gen_cond(cond)?;
+                    emit!(self, "  je .L.end.{}", label);
                 }
-                self.gen(node.then.unwrap());
+                self.gen_loop_body(node.then.unwrap(), label, format!(".L.continue.{}", label))?;
+                emit!(self, ".L.continue.{}:", label);
                 if let Some(postop) = node.postop {
-                    self.gen(postop);
+                    self.gen(postop)?;
+                    emit!(self, "  add rsp, 8");
+                    self.depth -= 1;
                 }
-                println!("  jmp .L.begin.{}", label);
-                println!(".L.end.{}:", label);
-                return;
+                emit!(self, "  jmp .L.begin.{}", label);
+                emit!(self, ".L.end.{}:", label);
+                return Ok(());
             }
-            NodeKind::NdFunc => {
-                let len = node.args.len();
-                for args in node.args {
-                    self.gen(Box::new(args));
+            NodeKind::NdSwitch => {
+                let label = self.new_label();
+                let cond = node.cond.unwrap();
+                let ty = cond.ty.clone().unwrap();
+                self.gen(cond)?;
+                self.pop("rax");
+                // the case values are compared as 64-bit numbers
+                if ty.size == 4 {
+                    if ty.is_unsigned {
+                        emit!(self, "  mov eax, eax");
+                    } else {
+                        emit!(self, "  movsxd rax, eax");
+                    }
                 }
 
-                for n in (0..len).rev() {
-                    println!("  pop {}", ARG_REGS8[n]);
+                let default = match node.has_default {
+                    true => format!(".L.default.{}", label),
+                    false => format!(".L.end.{}", label),
+                };
+                if is_dense(&node.cases) {
+                    self.gen_jump_table(&node.cases, label, &default);
+                } else {
+                    for (i, val) in node.cases.iter().enumerate() {
+                        emit!(self, "  mov rdi, {}", val);
+                        emit!(self, "  cmp rax, rdi");
+                        emit!(self, "  je .L.case.{}.{}", label, i);
+                    }
+                    emit!(self, "  jmp {}", default);
                 }
 
-                println!("  call {}", node.funcname);
-                println!("  push rax");
-                return;
+                self.breaks.push(format!(".L.end.{}", label));
+                self.switches.push(label);
+                self.gen(node.then.unwrap())?;
+                self.switches.pop();
+                self.breaks.pop();
+                emit!(self, ".L.end.{}:", label);
+                return Ok(());
             }
-            NodeKind::NdNum => {
-                println!("  push {}", node.val);
-                return;
-            }
-            NodeKind::NdLv => {
-                let size = node.ty.as_ref().unwrap().size;
-                let ty = node.ty.clone().unwrap().kind;
-
-                self.gen_lval(node);
-                if ty != TypeKind::TyArr {
-                    println!("  pop rax");
-                    if size == 4 {
-                        println!("  movsxd rax, dword ptr [rax]");
-                    } else {
-                        println!("  mov rax, [rax]");
+            NodeKind::NdCase => {
+                emit!(
+                    self,
+                    ".L.case.{}.{}:",
+                    self.switches.last().unwrap(),
+                    node.val
+                );
+                self.gen(node.lhs.unwrap())?;
+                return Ok(());
+            }
+            NodeKind::NdDefault => {
+                emit!(self, ".L.default.{}:", self.switches.last().unwrap());
+                self.gen(node.lhs.unwrap())?;
+                return Ok(());
+            }
+            NodeKind::NdBreak => {
+                emit!(self, "  jmp {}", self.breaks.last().unwrap());
+                return Ok(());
+            }
+            NodeKind::NdContinue => {
+                emit!(self, "  jmp {}", self.continues.last().unwrap());
+                return Ok(());
+            }
+            NodeKind::NdGoto => {
+                emit!(self, "  jmp .L.label.{}.{}", self.func_name, node.label);
+                return Ok(());
+            }
+            NodeKind::NdLabel => {
+                emit!(self, ".L.label.{}.{}:", self.func_name, node.label);
+                self.gen(node.lhs.unwrap())?;
+                return Ok(());
+            }
+            NodeKind::NdFunc => {
+                let locs = classify_args(node.args.iter().map(|arg| arg.ty.as_deref().unwrap()));
+                let (_, fp_args, stack_args) = count_args(&locs);
+
+                // rsp must be a multiple of 16 at the call, which it is at
+                // depth 0, so pad it if an odd number of values will be left
+                // on the stack
+                let padding = (self.depth + stack_args) % 2;
+                if padding == 1 {
+                    emit!(self, "  sub rsp, 8");
+                    self.depth += 1;
+                }
+
+                // the arguments are evaluated right to left: first those
+                // passed on the stack, so that the leftmost ends up on top,
+                // then those passed in registers, which are popped left to
+                // right
+                let (on_stack, in_regs): (Vec<_>, Vec<_>) = node
+                    .args
+                    .into_iter()
+                    .zip(locs)
+                    .partition(|(_, loc)| *loc == ArgLoc::Stack);
+                for (arg, _) in on_stack.into_iter().rev() {
+                    self.gen(Box::new(arg))?;
+                }
+                let mut locs = vec![];
+                for (arg, loc) in in_regs.into_iter().rev() {
+                    self.gen(Box::new(arg))?;
+                    locs.push(loc);
+                }
+                for loc in locs.iter().rev() {
+                    match loc {
+                        ArgLoc::Gp(i) => self.pop(ARG_REGS8[*i]),
+                        ArgLoc::Fp(i) => {
+                            self.pop("rax");
+                            emit!(self, "  movq xmm{}, rax", i);
+                        }
+                        ArgLoc::Stack => unreachable!(),
                     }
-                    println!("  push rax");
                 }
-                return;
+
+                // al holds the number of vector registers used by a call to a
+                // variadic function
+                if node.func_ty.unwrap().is_variadic {
+                    emit!(self, "  mov eax, {}", fp_args);
+                }
+                emit!(self, "  call {}", node.funcname);
+                if stack_args + padding > 0 {
+                    emit!(self, "  add rsp, {}", 8 * (stack_args + padding));
+                    self.depth -= stack_args + padding;
+                }
+
+                // only the low bits of a narrow return value are significant
+                let ty = node.ty.unwrap();
+                if ty.is_flonum() {
+                    emit!(self, "  movq rax, xmm0");
+                } else if ty.kind == TypeKind::TyBool {
+                    emit!(self, "  movzx eax, al");
+                } else if ty.size < 4 && ty.is_integer() {
+                    self.cast(&ty, &ty);
+                }
+                self.push("rax");
+                return Ok(());
             }
-            NodeKind::NdAs => {
-                self.gen_lval(node.lhs.unwrap());
-                self.gen(node.rhs.unwrap());
+            NodeKind::NdVaStart => {
+                // fill in the va_list that ap points to, past the named
+                // parameters
+                let params = &node.func_ty.as_ref().unwrap().params;
+                let (gp_args, fp_args, stack_args) = count_args(&classify_args(params.iter()));
+                let va_area = self.var_offsets[node.lvar.unwrap().id];
 
-                println!("  pop rdi");
-                println!("  pop rax");
-                if node.ty.unwrap().size == 4 {
-                    println!("  mov [rax], edi");
+                self.gen(node.lhs.unwrap())?;
+                self.pop("rax");
+                emit!(self, "  mov dword ptr [rax], {}", 8 * gp_args);
+                emit!(
+                    self,
+                    "  mov dword ptr [rax+4], {}",
+                    8 * ARG_REGS8.len() + 16 * fp_args
+                );
+                emit!(self, "  lea rdx, [rbp+{}]", 16 + 8 * stack_args);
+                emit!(self, "  mov [rax+8], rdx");
+                emit!(self, "  lea rdx, [rbp-{}]", va_area);
+                emit!(self, "  mov [rax+16], rdx");
+                self.push("rax");
+                return Ok(());
+            }
+            NodeKind::NdVaArg => {
+                // take the address of the next argument from the register
+                // save area while gp_offset, or fp_offset for a double, has
+                // room, then from the stack
+                let ty = node.ty.unwrap();
+                let (offset, step, limit) = if ty.is_flonum() {
+                    (4, 16, 8 * ARG_REGS8.len() + 16 * FP_ARG_REGS)
                 } else {
-                    println!("  mov [rax], rdi");
+                    (0, 8, 8 * ARG_REGS8.len())
+                };
+                let label = self.new_label();
+                self.gen(node.lhs.unwrap())?;
+                self.pop("rdi");
+                emit!(self, "  mov eax, [rdi+{}]", offset);
+                emit!(self, "  cmp eax, {}", limit);
+                emit!(self, "  jae .L.va_stack.{}", label);
+                emit!(self, "  lea edx, [rax+{}]", step);
+                emit!(self, "  mov [rdi+{}], edx", offset);
+                emit!(self, "  add rax, [rdi+16]");
+                emit!(self, "  jmp .L.va_end.{}", label);
+                emit!(self, ".L.va_stack.{}:", label);
+                emit!(self, "  mov rax, [rdi+8]");
+                emit!(self, "  lea rdx, [rax+8]");
+                emit!(self, "  mov [rdi+8], rdx");
+                emit!(self, ".L.va_end.{}:", label);
+                self.push("rax");
+                self.load(&ty);
+                return Ok(());
+            }
+            NodeKind::NdComma => {
+                self.gen(node.lhs.unwrap())?;
+                emit!(self, "  add rsp, 8");
+                self.depth -= 1;
+                self.gen(node.rhs.unwrap())?;
+                return Ok(());
+            }
+            NodeKind::NdCond => {
+                let label = self.new_label();
+                self.gen_cond(node.cond.unwrap())?;
+                emit!(self, "  je .L.else.{}", label);
+                self.gen(node.then.unwrap())?;
+                self.pop("rax");
+                emit!(self, "  jmp .L.end.{}", label);
+                emit!(self, ".L.else.{}:", label);
+                self.gen(node.els.unwrap())?;
+                self.pop("rax");
+                emit!(self, ".L.end.{}:", label);
+                self.push("rax");
+                return Ok(());
+            }
+            NodeKind::NdLogAnd | NodeKind::NdLogOr => {
+                // the right operand is evaluated only if the left one does
+                // not already decide the result
+                let label = self.new_label();
+                let (jump, short) = if node.kind == NodeKind::NdLogAnd {
+                    ("je", 0)
+                } else {
+                    ("jne", 1)
+                };
+                self.gen_cond(node.lhs.unwrap())?;
+                emit!(self, "  {} .L.short.{}", jump, label);
+                self.gen_cond(node.rhs.unwrap())?;
+                emit!(self, "  {} .L.short.{}", jump, label);
+                emit!(self, "  mov eax, {}", 1 - short);
+                emit!(self, "  jmp .L.end.{}", label);
+                emit!(self, ".L.short.{}:", label);
+                emit!(self, "  mov eax, {}", short);
+                emit!(self, ".L.end.{}:", label);
+                self.push("rax");
+                return Ok(());
+            }
+            NodeKind::NdNot => {
+                self.gen_cond(node.lhs.unwrap())?;
+                emit!(self, "  sete al");
+                emit!(self, "  movzx eax, al");
+                self.push("rax");
+                return Ok(());
+            }
+            NodeKind::NdBitNot => {
+                self.gen(node.lhs.unwrap())?;
+                self.pop("rax");
+                emit!(self, "  not rax");
+                self.push("rax");
+                return Ok(());
+            }
+            NodeKind::NdNum => {
+                match node.ty.as_ref().map(|ty| &ty.kind) {
+                    Some(TypeKind::TyFloat) => {
+                        emit!(self, "  mov eax, {}", (node.fval as f32).to_bits());
+                        self.push("rax");
+                    }
+                    Some(TypeKind::TyDouble) => {
+                        emit!(self, "  mov rax, {}", node.fval.to_bits());
+                        self.push("rax");
+                    }
+                    _ => {
+                        // push takes only a sign-extended 32-bit immediate
+                        let val = node.int_val();
+                        if val == val as i32 as i64 {
+                            self.push(&val.to_string());
+                        } else {
+                            emit!(self, "  movabs rax, {}", val);
+                            self.push("rax");
+                        }
+                    }
                 }
-                println!("  push rdi");
-                return;
+                return Ok(());
+            }
+            NodeKind::NdStr => {
+                emit!(self, "  lea rax, [rip+.L.str.{}]", node.val);
+                self.push("rax");
+                return Ok(());
+            }
+            NodeKind::NdLv | NodeKind::NdGv | NodeKind::NdMember => {
+                let ty = node.ty.clone().unwrap();
+                self.gen_lval(node, "left operand of assignment")?;
+                self.load(&ty);
+                return Ok(());
+            }
+            NodeKind::NdAs => {
+                self.gen_lval(node.lhs.unwrap(), "left operand of assignment")?;
+                self.gen(node.rhs.unwrap())?;
+                self.store(&node.ty.unwrap());
+                return Ok(());
             }
             NodeKind::NdAddr => {
-                self.gen_lval(node.lhs.unwrap());
-                return;
+                self.gen_lval(node.lhs.unwrap(), "unary '&' operand")?;
+                return Ok(());
             }
             NodeKind::NdDeref => {
-                self.gen(node.lhs.unwrap());
-                if node.ty.clone().unwrap().kind != TypeKind::TyArr {
-                    println!("  pop rax");
-                    if node.ty.unwrap().size == 4 {
-                        println!("  movsxd rax, dword ptr [rax]");
-                    } else {
-                        println!("  mov rax, [rax]");
-                    }
-                    println!("  push rax");
-                }
-                return;
+                self.gen(node.lhs.unwrap())?;
+                self.load(&node.ty.unwrap());
+                return Ok(());
+            }
+            NodeKind::NdCast => {
+                let from = node.lhs.as_ref().unwrap().ty.clone().unwrap();
+                self.gen(node.lhs.unwrap())?;
+                self.pop("rax");
+                self.cast(&from, &node.ty.unwrap());
+                self.push("rax");
+                return Ok(());
             }
             _ => {}
         }
 
-        match node.lhs {
-            Some(inner) => self.gen(inner),
-            _ => (),
+        // operate in 64 bits on longs and pointers, and in 32 bits otherwise
+        let lhs_ty = node.lhs.as_ref().unwrap().ty.clone().unwrap();
+        let rhs_ty = node.rhs.as_ref().unwrap().ty.clone().unwrap();
+        let is_pointer = lhs_ty.is_pointer() || rhs_ty.is_pointer();
+        let (ax, di) = if lhs_ty.size == 8 || is_pointer {
+            ("rax", "rdi")
+        } else {
+            ("eax", "edi")
+        };
+        let unsigned = lhs_ty.is_unsigned || is_pointer;
+
+        if let Some(inner) = node.lhs {
+            self.gen(inner)?;
         }
-        match node.rhs {
-            Some(inner) => self.gen(inner),
-            _ => (),
+        if let Some(inner) = node.rhs {
+            self.gen(inner)?;
         }
 
-        println!("  pop rdi");
-        println!("  pop rax");
+        self.pop("rdi");
+        self.pop("rax");
+
+        if lhs_ty.is_flonum() {
+            return self.gen_flonum_binary(node.kind, &lhs_ty, span);
+        }
 
         match node.kind {
             NodeKind::NdAdd => {
-                println!("  add rax, rdi");
+                emit!(self, "  add {}, {}", ax, di);
             }
             NodeKind::NdSub => {
-                println!("  sub rax, rdi");
+                emit!(self, "  sub {}, {}", ax, di);
             }
             NodeKind::NdMul => {
-                println!("  imul rax, rdi");
+                emit!(self, "  imul {}, {}", ax, di);
+            }
+            NodeKind::NdDiv | NodeKind::NdMod => {
+                if unsigned {
+                    emit!(self, "  xor edx, edx");
+                    emit!(self, "  div {}", di);
+                } else {
+                    emit!(self, "  {}", if ax == "rax" { "cqo" } else { "cdq" });
+                    emit!(self, "  idiv {}", di);
+                }
+                // the remainder is left in rdx
+                if node.kind == NodeKind::NdMod {
+                    emit!(self, "  mov rax, rdx");
+                }
+            }
+            NodeKind::NdBitAnd => {
+                emit!(self, "  and {}, {}", ax, di);
+            }
+            NodeKind::NdBitOr => {
+                emit!(self, "  or {}, {}", ax, di);
             }
-            NodeKind::NdDiv => {
-                println!("  cqo");
-                println!("  idiv rdi");
+            NodeKind::NdBitXor => {
+                emit!(self, "  xor {}, {}", ax, di);
+            }
+            NodeKind::NdShl => {
+                emit!(self, "  mov rcx, rdi");
+                emit!(self, "  shl {}, cl", ax);
+            }
+            NodeKind::NdShr => {
+                emit!(self, "  mov rcx, rdi");
+                emit!(
+                    self,
+                    "  {} {}, cl",
+                    if lhs_ty.is_unsigned { "shr" } else { "sar" },
+                    ax
+                );
             }
             NodeKind::NdMt => {
-                println!("  cmp rdi, rax");
-                println!("  setl al");
-                println!("  movzb rax, al");
+                emit!(self, "  cmp {}, {}", ax, di);
+                emit!(self, "  {} al", if unsigned { "seta" } else { "setg" });
+                emit!(self, "  movzb rax, al");
             }
             NodeKind::NdLt => {
-                println!("  cmp rax, rdi");
-                println!("  setl al");
-                println!("  movzb rax, al");
+                emit!(self, "  cmp {}, {}", ax, di);
+                emit!(self, "  {} al", if unsigned { "setb" } else { "setl" });
+                emit!(self, "  movzb rax, al");
             }
             NodeKind::NdOm => {
-                println!("  cmp rdi, rax");
-                println!("  setle al");
-                println!("  movzb rax, al");
+                emit!(self, "  cmp {}, {}", ax, di);
+                emit!(self, "  {} al", if unsigned { "setae" } else { "setge" });
+                emit!(self, "  movzb rax, al");
             }
             NodeKind::NdOl => {
-                println!("  cmp rax, rdi");
-                println!("  setle al");
-                println!("  movzb rax, al");
+                emit!(self, "  cmp {}, {}", ax, di);
+                emit!(self, "  {} al", if unsigned { "setbe" } else { "setle" });
+                emit!(self, "  movzb rax, al");
             }
             NodeKind::NdEq => {
-                println!("  cmp rax, rdi");
-                println!("  sete al");
-                println!("  movzb rax, al");
+                emit!(self, "  cmp {}, {}", ax, di);
+                emit!(self, "  sete al");
+                emit!(self, "  movzb rax, al");
             }
             NodeKind::NdNe => {
-                println!("  cmp rax, rdi");
-                println!("  setne al");
-                println!("  movzb rax, al");
+                emit!(self, "  cmp {}, {}", ax, di);
+                emit!(self, "  setne al");
+                emit!(self, "  movzb rax, al");
             }
             _ => {
-                eprintln!("unknown node");
-                process::exit(1);
+                return Err(CompileError::new(
+                    ErrorKind::Internal,
+                    span,
+                    "unknown node".to_string(),
+                ));
             }
         }
 
-        println!("  push rax");
+        self.push("rax");
+
+        Ok(())
     }
 
-    pub fn codegen(&mut self, parser: Parser) {
-        println!(".intel_syntax noprefix");
+    // Emits initialized globals to .data and the rest to .bss.
+    fn emit_data(&mut self, globals: &[Global]) {
+        for global in globals {
+            let name = &global.gvar.name;
+            let ty = &global.gvar.ty;
+            let data = match &global.data {
+                Some(data) => data,
+                None => continue,
+            };
+
+            emit!(self, ".data");
+            if !global.is_static {
+                emit!(self, ".global {}", name);
+            }
+            emit!(self, ".align {}", ty.align());
+            emit!(self, "{}:", name);
+
+            let mut relocs = global.relocs.iter().peekable();
+            let mut bytes = vec![];
+            let mut pos = 0;
+            while pos < data.len() {
+                if let Some(reloc) = relocs.next_if(|reloc| reloc.offset == pos) {
+                    if !bytes.is_empty() {
+                        emit!(self, "  .byte {}", bytes.join(","));
+                        bytes.clear();
+                    }
+                    emit!(self, "  .quad {}{:+}", reloc.label, reloc.addend);
+                    pos += 8;
+                    continue;
+                }
+                bytes.push(data[pos].to_string());
+                pos += 1;
+            }
+            if !bytes.is_empty() {
+                emit!(self, "  .byte {}", bytes.join(","));
+            }
+        }
+
+        for global in globals.iter().filter(|global| global.data.is_none()) {
+            emit!(self, ".bss");
+            if !global.is_static {
+                emit!(self, ".global {}", global.gvar.name);
+            }
+            emit!(self, ".align {}", global.gvar.ty.align());
+            emit!(self, "{}:", global.gvar.name);
+            emit!(self, "  .zero {}", global.gvar.ty.size);
+        }
+    }
+
+    pub fn codegen(&mut self, parser: Parser) -> Result<String, CompileError> {
+        emit!(self, ".intel_syntax noprefix");
+
+        emit!(self, ".section .rodata");
+        for (i, contents) in parser.strings.iter().enumerate() {
+            emit!(self, ".L.str.{}:", i);
+            let bytes: Vec<String> = contents.iter().map(|b| b.to_string()).collect();
+            emit!(self, "  .byte {}", bytes.join(","));
+        }
+
+        self.emit_data(&parser.globals);
+
+        emit!(self, ".text");
         for function in parser.functions {
-            println!(".global {}", function.name);
-            println!("{}:", function.name);
+            if !function.is_static {
+                emit!(self, ".global {}", function.name);
+            }
+            emit!(self, "{}:", function.name);
+            self.func_name = function.name.clone();
 
             self.var_offsets = vec![0; function.locals.len()];
             let mut stack_size = 0;
             for i in (0..function.locals.len()).rev() {
-                println!("# ----- {}", function.locals[i].name);
-                stack_size += function.locals[i].ty.size;
+                emit!(self, "# ----- {}", function.locals[i].name);
+                let ty = &function.locals[i].ty;
+                stack_size = align_to(stack_size + ty.size, ty.align());
                 self.var_offsets[i] = stack_size;
             }
 
             // prologue
-            println!("  push rbp");
-            println!("  mov rbp, rsp");
-            println!("  sub rsp, {}", align(stack_size, 16));
+            emit!(self, "  push rbp");
+            emit!(self, "  mov rbp, rsp");
+            emit!(self, "  sub rsp, {}", align(stack_size, 16));
 
-            for n in 0..function.paramnum {
-                if function.locals[n].ty.size == 4 {
-                    println!("  mov [rbp-{}], {}", self.var_offsets[n], ARG_REGS4[n]);
-                } else {
-                    println!("  mov [rbp-{}], {}", self.var_offsets[n], ARG_REGS8[n]);
+            if let Some(va_area) = &function.va_area {
+                // the register save area holds the six general-purpose
+                // registers, then the eight vector registers
+                let offset = self.var_offsets[va_area.id];
+                for (i, reg) in ARG_REGS8.iter().enumerate() {
+                    emit!(self, "  mov [rbp-{}], {}", offset - 8 * i, reg);
+                }
+                for i in 0..FP_ARG_REGS {
+                    let offset = offset - 8 * ARG_REGS8.len() - 16 * i;
+                    emit!(self, "  movsd [rbp-{}], xmm{}", offset, i);
                 }
             }
 
+            let params = &function.locals[..function.paramnum];
+            let locs = classify_args(params.iter().map(|param| &param.ty));
+            let mut stack_args = 0;
+            for (n, loc) in locs.iter().enumerate() {
+                let ty = &params[n].ty;
+                let offset = self.var_offsets[n];
+                let reg = match *loc {
+                    ArgLoc::Gp(i) => match ty.size {
+                        1 => ARG_REGS1[i],
+                        2 => ARG_REGS2[i],
+                        4 => ARG_REGS4[i],
+                        _ => ARG_REGS8[i],
+                    },
+                    ArgLoc::Fp(i) => {
+                        emit!(self, "  mov{} [rbp-{}], xmm{}", sse_suffix(ty), offset, i);
+                        continue;
+                    }
+                    ArgLoc::Stack => {
+                        // the rest of the arguments were pushed by the
+                        // caller, right to left, above the return address
+                        // and saved rbp
+                        emit!(self, "  mov rax, [rbp+{}]", 16 + 8 * stack_args);
+                        stack_args += 1;
+                        match ty.size {
+                            1 => "al",
+                            2 => "ax",
+                            4 => "eax",
+                            _ => "rax",
+                        }
+                    }
+                };
+                emit!(self, "  mov [rbp-{}], {}", offset, reg);
+            }
+
             for node in function.body {
-                self.gen(Box::new(node));
-                println!("  pop rax");
+                self.gen(Box::new(node))?;
             }
 
             // epilogue
-            println!("  mov rsp, rbp");
-            println!("  pop rbp");
+            emit!(self, "  mov rsp, rbp");
+            emit!(self, "  pop rbp");
 
-            println!("  ret");
+            emit!(self, "  ret");
         }
+
+        // the code does not need an executable stack
+        emit!(self, ".section .note.GNU-stack,\"\",@progbits");
+
+        Ok(std::mem::take(&mut self.out))
     }
 
     pub fn new() -> Self {
         Self {
+            out: String::new(),
             label: 0,
             var_offsets: vec![],
+            depth: 0,
+            breaks: vec![],
+            continues: vec![],
+            switches: vec![],
+            func_name: String::new(),
         }
     }
 }
 
+// Whether a switch is better compiled to a jump table than to a chain of
+// compares: it has a few cases, and they fill at least half of their range.
+fn is_dense(cases: &[i64]) -> bool {
+    if cases.len() < 4 {
+        return false;
+    }
+    let min = *cases.iter().min().unwrap() as i128;
+    let max = *cases.iter().max().unwrap() as i128;
+    max - min < 2 * cases.len() as i128
+}
+
 fn align(mut n: usize, align: usize) -> usize {
     if n < align {
         return align;
     }
-    while n % align != 0 {
+    while !n.is_multiple_of(align) {
         n += 1;
     }
-    return n;
+    n
 }
@@ -0,0 +1,94 @@
+use std::fmt;
+
+use crate::tokenize::Span;
+
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+impl Source {
+    pub fn new(name: String, text: String) -> Self {
+        Self { name, text }
+    }
+
+    fn line_at(&self, span: Span) -> &str {
+        let start = span.lo - (span.col - 1);
+        let end = self.text[start..]
+            .find('\n')
+            .map_or(self.text.len(), |n| start + n);
+
+        &self.text[start..end]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    InvalidToken,    // the tokenizer cannot make a token
+    UnexpectedToken, // the parser expected something else
+    Undeclared,      // use of an undeclared name
+    Redeclaration,   // a name declared twice in the same scope
+    NotLvalue,       // assignment or & to a non-lvalue
+    TypeMismatch,    // an operand of the wrong type
+    NotConstant,     // a static initializer that is not a constant expression
+    Preprocessor,    // a bad directive or macro invocation
+    TooManyErrors,   // the -fmax-errors limit was reached
+    Internal,        // a bug in the compiler
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub kind: ErrorKind,
+    pub span: Span,
+    pub msg: String,
+}
+
+impl CompileError {
+    pub fn new(kind: ErrorKind, span: Span, msg: String) -> Self {
+        Self { kind, span, msg }
+    }
+
+    // Formats the error in the style used by gcc and clang:
+    //
+    //   foo.c:1:22: error: expected ; but got }
+    //    1 | int main() { return 0 }
+    //      |                       ^
+    pub fn render(&self, files: &[Source]) -> String {
+        if self.kind == ErrorKind::TooManyErrors {
+            return format!("{}\n", self.msg);
+        }
+
+        let span = self.span;
+        let src = &files[span.file];
+        let line = src.line_at(span);
+        let gutter = span.line.to_string();
+
+        // keep tabs so that the caret lines up with the source line
+        let indent: String = line[..span.col - 1]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = (span.hi - span.lo).clamp(1, line.len() + 2 - span.col);
+
+        format!(
+            "{}:{}:{}: error: {}\n {} | {}\n {} | {}^{}\n",
+            src.name,
+            span.line,
+            span.col,
+            self.msg,
+            gutter,
+            line,
+            " ".repeat(gutter.len()),
+            indent,
+            "~".repeat(width - 1)
+        )
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.col, self.msg)
+    }
+}
+
+impl std::error::Error for CompileError {}
@@ -0,0 +1,6 @@
+pub mod codegen;
+pub mod error;
+pub mod parse;
+pub mod preprocess;
+pub mod tokenize;
+pub mod types;
@@ -1,28 +1,351 @@
 use std::env;
-use std::process;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{self, Command};
 
-mod codegen;
-mod parse;
-mod tokenize;
-mod types;
+use mmcc2::codegen::Generator;
+use mmcc2::error::{CompileError, Source};
+use mmcc2::parse::Parser;
+use mmcc2::preprocess::{format_tokens, Preprocessor};
 
-use crate::codegen::Generator;
-use crate::parse::Parser;
-use crate::tokenize::tokenize;
+#[derive(PartialEq)]
+enum Mode {
+    Preprocess, // -E
+    Assembly,   // -S
+    Object,     // -c
+    Link,
+}
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("wrong the number of arguments");
-        process::exit(1);
+enum Input {
+    File(String),
+    Stdin,
+    Inline(String),    // -e '<code>'
+    Asm(String),       // foo.s
+    LinkerArg(String), // foo.o, libfoo.a, -lfoo, -Ldir
+}
+
+struct Options {
+    mode: Mode,
+    inputs: Vec<Input>,
+    output: Option<String>,
+    max_errors: usize,
+    include_paths: Vec<String>,
+    defines: Vec<(String, Option<String>)>, // -D name=val, or -U name as None
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: mmcc2 [-E | -S | -c] [-o <file>] [-I<dir>] [-D<name>[=<val>]] [-U<name>] \
+         [-fmax-errors=N] [-l<lib>] [-L<dir>] (<file>... | - | -e <code>)"
+    );
+    process::exit(1);
+}
+
+fn fatal(msg: &str) -> ! {
+    eprintln!("mmcc2: error: {}", msg);
+    process::exit(1);
+}
+
+fn parse_args(args: &[String]) -> Options {
+    let mut opts = Options {
+        mode: Mode::Link,
+        inputs: vec![],
+        output: None,
+        max_errors: 0,
+        include_paths: vec![],
+        defines: vec![],
+    };
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+
+        // options whose value is either joined or the next argument
+        let (flag, val) = match ["-o", "-e", "-l", "-L", "-I", "-D", "-U"]
+            .iter()
+            .find(|flag| arg.starts_with(*flag))
+        {
+            Some(flag) if arg.len() > 2 => (*flag, arg[2..].to_string()),
+            Some(flag) => {
+                if i == args.len() {
+                    fatal(&format!("missing argument to '{}'", arg));
+                }
+                i += 1;
+                (*flag, args[i - 1].clone())
+            }
+            None => ("", String::new()),
+        };
+
+        if !flag.is_empty() {
+            match flag {
+                "-o" => opts.output = Some(val),
+                "-e" => opts.inputs.push(Input::Inline(val)),
+                "-I" => opts.include_paths.push(val),
+                "-D" => match val.split_once('=') {
+                    Some((name, val)) => {
+                        opts.defines.push((name.to_string(), Some(val.to_string())))
+                    }
+                    None => opts.defines.push((val, Some("1".to_string()))),
+                },
+                "-U" => opts.defines.push((val, None)),
+                _ => opts
+                    .inputs
+                    .push(Input::LinkerArg(format!("{}{}", flag, val))),
+            }
+        } else if arg == "-E" {
+            opts.mode = Mode::Preprocess;
+        } else if arg == "-S" {
+            opts.mode = Mode::Assembly;
+        } else if arg == "-c" {
+            opts.mode = Mode::Object;
+        } else if let Some(n) = arg.strip_prefix("-fmax-errors=") {
+            opts.max_errors = n.parse().unwrap_or_else(|_| usage());
+        } else if arg.starts_with("-Wl,") {
+            opts.inputs.push(Input::LinkerArg(arg.clone()));
+        } else if arg.starts_with("-O")
+            || arg.starts_with("-g")
+            || arg.starts_with("-W")
+            || arg.starts_with("-std=")
+        {
+            // accepted for compatibility with cc, but has no effect
+        } else if arg == "-" {
+            opts.inputs.push(Input::Stdin);
+        } else if arg.starts_with('-') {
+            fatal(&format!("unrecognized command-line option '{}'", arg));
+        } else if arg.ends_with(".s") {
+            opts.inputs.push(Input::Asm(arg.clone()));
+        } else if arg.ends_with(".c") {
+            opts.inputs.push(Input::File(arg.clone()));
+        } else {
+            opts.inputs.push(Input::LinkerArg(arg.clone()));
+        }
+    }
+
+    if opts.inputs.is_empty() {
+        usage();
+    }
+    // linker arguments are not compiled, so they do not count here
+    let sources = opts
+        .inputs
+        .iter()
+        .filter(|input| !matches!(input, Input::LinkerArg(_)))
+        .count();
+    if opts.mode != Mode::Link && opts.output.is_some() && sources > 1 {
+        fatal("cannot specify '-o' with '-c' or '-S' with multiple files");
     }
 
-    let input = &args[1];
-    let tokens = tokenize(input.to_string());
+    opts
+}
+
+fn read_input(input: &Input) -> Result<Source, String> {
+    match input {
+        Input::File(path) => match fs::read_to_string(path) {
+            Ok(text) => Ok(Source::new(path.clone(), text)),
+            Err(err) => Err(format!("cannot open {}: {}", path, err)),
+        },
+        Input::Stdin => {
+            let mut text = String::new();
+            if let Err(err) = io::stdin().read_to_string(&mut text) {
+                return Err(format!("cannot read standard input: {}", err));
+            }
+            Ok(Source::new("<stdin>".to_string(), text))
+        }
+        Input::Inline(code) => Ok(Source::new("<input>".to_string(), code.clone())),
+        Input::Asm(_) | Input::LinkerArg(_) => unreachable!(),
+    }
+}
+
+// foo/bar.c is compiled to bar.s or bar.o in the current directory, as cc
+// does. Inline code and stdin have no name to derive from; their assembly
+// goes to stdout and their object file to a.o. Preprocessed output goes to
+// stdout unless -o is given.
+fn output_path(input: &Input, opts: &Options) -> Option<String> {
+    let to_stdout = opts.mode == Mode::Assembly || opts.mode == Mode::Preprocess;
+    if let Some(path) = &opts.output {
+        if path == "-" && to_stdout {
+            return None;
+        }
+        return Some(path.clone());
+    }
+    if opts.mode == Mode::Preprocess {
+        return None;
+    }
+
+    let ext = if opts.mode == Mode::Assembly {
+        "s"
+    } else {
+        "o"
+    };
+    match input {
+        Input::File(path) | Input::Asm(path) => {
+            let stem = Path::new(path).file_stem().unwrap_or_default();
+            Some(Path::new(stem).with_extension(ext).display().to_string())
+        }
+        _ if opts.mode == Mode::Object => Some("a.o".to_string()),
+        _ => None,
+    }
+}
+
+// Temporary files live next to each other in the system temp directory and
+// are removed when this is dropped, before the driver exits.
+struct TempFiles {
+    paths: Vec<String>,
+}
+
+impl TempFiles {
+    fn create(&mut self, ext: &str) -> String {
+        let path = env::temp_dir()
+            .join(format!(
+                "mmcc2-{}-{}.{}",
+                process::id(),
+                self.paths.len(),
+                ext
+            ))
+            .display()
+            .to_string();
+        self.paths.push(path.clone());
+        path
+    }
+}
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn run(cmd: &mut Command) -> Result<(), String> {
+    let name = format!("{:?}", cmd.get_program());
+    match cmd.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(_) => Err(format!("{} failed", name)),
+        Err(err) => Err(format!("cannot run {}: {}", name, err)),
+    }
+}
+
+fn assemble(input: &str, output: &str) -> Result<(), String> {
+    run(Command::new("as").arg("-o").arg(output).arg(input))
+}
+
+fn write_output(path: Option<String>, asm: &str) -> Result<(), String> {
+    let written = match path {
+        Some(path) => fs::write(&path, asm).map_err(|err| (path, err)),
+        None => io::stdout()
+            .write_all(asm.as_bytes())
+            .map_err(|err| ("standard output".to_string(), err)),
+    };
+    written.map_err(|(path, err)| format!("cannot write {}: {}", path, err))
+}
+
+fn new_preprocessor(opts: &Options) -> Result<Preprocessor, String> {
+    let mut pp = Preprocessor::new();
+    pp.include_paths = opts.include_paths.clone();
+    for (name, val) in &opts.defines {
+        match val {
+            Some(val) => {
+                if let Err(err) = pp.define(name, val) {
+                    return Err(format!("-D{}: {}", name, err.msg));
+                }
+            }
+            None => pp.undef(name),
+        }
+    }
+    Ok(pp)
+}
+
+// Compiles a source file to assembly, or to preprocessed text with -E.
+fn compile(
+    pp: &mut Preprocessor,
+    src: Source,
+    opts: &Options,
+) -> Result<String, Vec<CompileError>> {
+    let tokens = pp.preprocess(src).map_err(|err| vec![err])?;
+    if opts.mode == Mode::Preprocess {
+        return Ok(format_tokens(&tokens));
+    }
 
     let mut parser = Parser::new(&tokens);
-    parser.program();
+    parser.max_errors = opts.max_errors;
+    parser.program()?;
 
     let mut generator = Generator::new();
-    generator.codegen(parser);
+    generator.codegen(parser).map_err(|err| vec![err])
+}
+
+// Compiles, assembles and links the inputs, keeping intermediate files in
+// temps. Returns false if diagnostics were reported for any input.
+fn build(opts: &Options, temps: &mut TempFiles) -> Result<bool, String> {
+    let mut link_args = vec![];
+    let mut failed = false;
+
+    for input in &opts.inputs {
+        let asm_path = match input {
+            Input::LinkerArg(arg) => {
+                link_args.push(arg.clone());
+                continue;
+            }
+            Input::Asm(path) => path.clone(),
+            _ => {
+                let mut pp = new_preprocessor(opts)?;
+                let asm = match compile(&mut pp, read_input(input)?, opts) {
+                    Ok(asm) => asm,
+                    Err(errs) => {
+                        for err in errs {
+                            eprint!("{}", err.render(&pp.files));
+                        }
+                        failed = true;
+                        continue;
+                    }
+                };
+
+                if opts.mode == Mode::Assembly || opts.mode == Mode::Preprocess {
+                    write_output(output_path(input, opts), &asm)?;
+                    continue;
+                }
+
+                let path = temps.create("s");
+                write_output(Some(path.clone()), &asm)?;
+                path
+            }
+        };
+
+        match opts.mode {
+            Mode::Preprocess | Mode::Assembly => {}
+            Mode::Object => assemble(&asm_path, &output_path(input, opts).unwrap())?,
+            Mode::Link => {
+                let obj = temps.create("o");
+                assemble(&asm_path, &obj)?;
+                link_args.push(obj);
+            }
+        }
+    }
+
+    if failed {
+        return Ok(false);
+    }
+
+    if opts.mode == Mode::Link {
+        let output = opts.output.as_deref().unwrap_or("a.out");
+        run(Command::new("cc").arg("-o").arg(output).args(&link_args))?;
+    }
+    Ok(true)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let opts = parse_args(&args);
+
+    let mut temps = TempFiles { paths: vec![] };
+    let result = build(&opts, &mut temps);
+    drop(temps);
+
+    match result {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(msg) => fatal(&msg),
+    }
 }
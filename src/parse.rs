@@ -1,37 +1,61 @@
-use std::process;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 
-use crate::tokenize::{Token, TokenKind};
-use crate::types::{Type, TypeKind};
+use crate::error::{CompileError, ErrorKind};
+use crate::tokenize::{Kw, Span, Token, TokenKind, P};
+use crate::types::{Member, Type, TypeKind};
 
-#[derive(PartialEq, Clone)]
+#[derive(Default, PartialEq, Clone)]
 pub enum NodeKind {
-    NdAdd,   // +
-    NdSub,   // -
-    NdMul,   // *
-    NdDiv,   // /
-    NdNum,   // number
-    NdMt,    // more than >
-    NdLt,    // less than <
-    NdOm,    // or more >=
-    NdOl,    // or less <=
-    NdEq,    // equal
-    NdNe,    // not equal
-    NdAs,    // assign =
-    NdLv,    // local variable
-    NdAddr,  // address &
-    NdDeref, // dereference *
-    NdIf,    // if
-    NdWhile, // while
-    NdFor,   // for
-    NdBlock, // block {}
-    NdFunc,  // function
-    NdRt,    // return
-}
-
-impl Default for NodeKind {
-    fn default() -> Self {
-        NodeKind::NdNum
-    }
+    NdAdd,    // +
+    NdSub,    // -
+    NdMul,    // *
+    NdDiv,    // /
+    NdMod,    // %
+    NdBitAnd, // &
+    NdBitOr,  // |
+    NdBitXor, // ^
+    NdBitNot, // ~
+    NdShl,    // <<
+    NdShr,    // >>
+    NdLogAnd, // &&
+    NdLogOr,  // ||
+    NdNot,    // !
+    NdCond,   // ?:
+    NdComma,  // ,
+    #[default]
+    NdNum, // number
+    NdStr,    // string literal
+    NdMt,     // more than >
+    NdLt,     // less than <
+    NdOm,     // or more >=
+    NdOl,     // or less <=
+    NdEq,     // equal
+    NdNe,     // not equal
+    NdAs,     // assign =
+    NdLv,     // local variable
+    NdGv,     // global variable
+    NdAddr,   // address &
+    NdDeref,  // dereference *
+    NdCast,   // type conversion
+    NdMember, // . (struct member access)
+    NdIf,     // if
+    NdWhile,  // while
+    NdFor,    // for
+    NdDo,     // do ... while
+    NdSwitch, // switch
+    NdCase,   // case label, val is its index in the switch's cases
+    NdDefault, // default label
+    NdBreak,  // break
+    NdContinue, // continue
+    NdGoto,   // goto
+    NdLabel,  // labeled statement
+    NdBlock,  // block {}
+    NdExprStmt, // expression statement
+    NdFunc,   // function
+    NdVaStart, // va_start
+    NdVaArg,  // va_arg
+    NdRt,     // return
 }
 
 #[derive(Default, PartialEq, Clone)]
@@ -40,8 +64,11 @@ pub struct Node {
     pub ty: Option<Box<Type>>,
     pub lhs: Option<Box<Node>>,
     pub rhs: Option<Box<Node>>,
-    pub val: u32,
+    pub val: u64,
+    pub fval: f64, // value of a floating-point number
     pub lvar: Option<Box<LVar>>,
+    pub gvar: Option<Box<GVar>>,
+    pub member: Option<Box<Member>>,
     pub cond: Option<Box<Node>>,
     pub then: Option<Box<Node>>,
     pub els: Option<Box<Node>>,
@@ -49,53 +76,120 @@ pub struct Node {
     pub postop: Option<Box<Node>>,
     pub blocks: Vec<Node>,
     pub funcname: String,
+    pub label: String,     // target of a goto, or name of a labeled statement
+    pub cases: Vec<i64>,   // case values of a switch, in order of appearance
+    pub has_default: bool, // whether a switch has a default label
+    pub func_ty: Option<Box<Type>>, // type of the function called, or for va_start the enclosing one
     pub args: Vec<Node>,
+    pub span: Span,
 }
 
 impl Node {
-    fn new_node(kind: NodeKind) -> Self {
+    fn new_node(kind: NodeKind, span: Span) -> Self {
         Self {
-            kind: kind,
+            kind,
+            span,
             ..Default::default()
         }
     }
 
-    fn new_binary(kind: NodeKind, lhs: Box<Node>, rhs: Box<Node>) -> Self {
+    fn new_binary(kind: NodeKind, lhs: Box<Node>, rhs: Box<Node>, span: Span) -> Self {
         Self {
             lhs: Some(lhs),
             rhs: Some(rhs),
-            ..Node::new_node(kind)
+            ..Node::new_node(kind, span)
         }
     }
 
-    fn new_unary(kind: NodeKind, expr: Box<Node>) -> Self {
+    fn new_unary(kind: NodeKind, expr: Box<Node>, span: Span) -> Self {
         Self {
             lhs: Some(expr),
-            ..Node::new_node(kind)
+            ..Node::new_node(kind, span)
         }
     }
 
-    fn new_node_lv(lvar: Box<LVar>) -> Self {
+    fn new_node_lv(lvar: Box<LVar>, span: Span) -> Self {
         Self {
             ty: Some(Box::new(lvar.ty.clone())),
             lvar: Some(lvar),
-            ..Node::new_node(NodeKind::NdLv)
+            ..Node::new_node(NodeKind::NdLv, span)
+        }
+    }
+
+    fn new_node_gv(gvar: Box<GVar>, span: Span) -> Self {
+        Self {
+            ty: Some(Box::new(gvar.ty.clone())),
+            gvar: Some(gvar),
+            ..Node::new_node(NodeKind::NdGv, span)
+        }
+    }
+
+    fn new_member(mut lhs: Box<Node>, member: Member, ty: Type, span: Span) -> Self {
+        lhs.check_type();
+        Self {
+            ty: Some(Box::new(ty)),
+            member: Some(Box::new(member)),
+            ..Node::new_unary(NodeKind::NdMember, lhs, span)
+        }
+    }
+
+    fn new_deref(mut expr: Box<Node>, span: Span) -> Result<Self, CompileError> {
+        expr.check_type();
+        if !expr.ty.as_ref().unwrap().is_pointer() {
+            return Err(CompileError::new(
+                ErrorKind::TypeMismatch,
+                span,
+                "invalid type argument of unary '*'".to_string(),
+            ));
         }
+        Ok(Node::new_unary(NodeKind::NdDeref, expr, span))
     }
 
-    fn new_node_num(val: u32) -> Self {
+    // The value of an integer constant, extended from its type to 64 bits.
+    pub fn int_val(&self) -> i64 {
+        let ty = self.ty.as_deref();
+        match ty.map_or((4, false), |ty| (ty.size, ty.is_unsigned)) {
+            (8, _) => self.val as i64,
+            (_, true) => self.val as u32 as i64,
+            _ => self.val as i32 as i64,
+        }
+    }
+
+    fn new_node_num(val: u64, span: Span) -> Self {
+        Self {
+            val,
+            ..Node::new_node(NodeKind::NdNum, span)
+        }
+    }
+
+    fn new_node_long(val: u64, span: Span) -> Self {
+        Self {
+            ty: Some(Box::new(Type::new_long())),
+            ..Node::new_node_num(val, span)
+        }
+    }
+
+    fn new_cast(mut expr: Box<Node>, ty: Type) -> Self {
+        expr.check_type();
+        let span = expr.span;
         Self {
-            val: val,
-            ..Node::new_node(NodeKind::NdNum)
+            ty: Some(Box::new(ty)),
+            ..Node::new_unary(NodeKind::NdCast, expr, span)
         }
     }
 
-    fn new_add(mut lhs: Box<Node>, mut rhs: Box<Node>) -> Self {
+    fn new_add(mut lhs: Box<Node>, mut rhs: Box<Node>, span: Span) -> Result<Self, CompileError> {
         lhs.check_type();
         rhs.check_type();
+        Node::check_arith_operands(&lhs, &rhs, P::Plus, span)?;
+
+        if lhs.ty.as_ref().unwrap().is_numeric() && rhs.ty.as_ref().unwrap().is_numeric() {
+            return Ok(Node::new_binary(NodeKind::NdAdd, lhs, rhs, span));
+        }
 
-        if lhs.ty.as_ref().unwrap().is_integer() && rhs.ty.as_ref().unwrap().is_integer() {
-            return Node::new_binary(NodeKind::NdAdd, lhs, rhs);
+        // num + ptr is ptr + num
+        if lhs.ty.as_ref().unwrap().is_integer() && rhs.ty.as_ref().unwrap().is_pointer() {
+            return Node::new_add(rhs, lhs, span);
         }
 
         if lhs.kind == NodeKind::NdAddr
@@ -104,22 +198,40 @@ impl Node {
         {
             let size = lhs.clone().ty.unwrap().ptr_to.unwrap().size;
 
+            // the offset is computed in 64 bits, so that a negative index works
             rhs = Box::new(Node::new_binary(
                 NodeKind::NdMul,
                 rhs,
-                Box::new(Node::new_node_num(size as u32)),
+                Box::new(Node::new_node_long(size as u64, span)),
+                span,
             ))
         }
 
-        return Node::new_binary(NodeKind::NdAdd, lhs, rhs);
+        Ok(Node::new_binary(NodeKind::NdAdd, lhs, rhs, span))
     }
 
-    fn new_sub(mut lhs: Box<Node>, mut rhs: Box<Node>) -> Self {
+    fn new_sub(mut lhs: Box<Node>, mut rhs: Box<Node>, span: Span) -> Result<Self, CompileError> {
         lhs.check_type();
         rhs.check_type();
+        Node::check_arith_operands(&lhs, &rhs, P::Minus, span)?;
+
+        if lhs.ty.as_ref().unwrap().is_numeric() && rhs.ty.as_ref().unwrap().is_numeric() {
+            return Ok(Node::new_binary(NodeKind::NdSub, lhs, rhs, span));
+        }
 
-        if lhs.ty.as_ref().unwrap().is_integer() && rhs.ty.as_ref().unwrap().is_integer() {
-            return Node::new_binary(NodeKind::NdSub, lhs, rhs);
+        // ptr - ptr is the number of elements between the two
+        if lhs.ty.as_ref().unwrap().is_pointer() && rhs.ty.as_ref().unwrap().is_pointer() {
+            let size = lhs.ty.as_ref().unwrap().ptr_to.as_ref().unwrap().size;
+            let diff = Node {
+                ty: Some(Box::new(Type::new_long())),
+                ..Node::new_binary(NodeKind::NdSub, lhs, rhs, span)
+            };
+            return Ok(Node::new_binary(
+                NodeKind::NdDiv,
+                Box::new(diff),
+                Box::new(Node::new_node_long(size as u64, span)),
+                span,
+            ));
         }
 
         if lhs.kind == NodeKind::NdAddr
@@ -131,15 +243,93 @@ impl Node {
             rhs = Box::new(Node::new_binary(
                 NodeKind::NdMul,
                 rhs,
-                Box::new(Node::new_node_num(size as u32)),
+                Box::new(Node::new_node_long(size as u64, span)),
+                span,
             ))
         }
 
-        return Node::new_binary(NodeKind::NdSub, lhs, rhs);
+        Ok(Node::new_binary(NodeKind::NdSub, lhs, rhs, span))
+    }
+
+    fn new_comparison(
+        op: P,
+        mut lhs: Box<Node>,
+        mut rhs: Box<Node>,
+        span: Span,
+    ) -> Result<Self, CompileError> {
+        lhs.check_type();
+        rhs.check_type();
+        if lhs.ty.as_ref().unwrap().is_struct() || rhs.ty.as_ref().unwrap().is_struct() {
+            return Err(CompileError::new(
+                ErrorKind::TypeMismatch,
+                span,
+                format!("invalid operands to binary {}", op.as_str()),
+            ));
+        }
+        let kind = match op {
+            P::Lt => NodeKind::NdLt,
+            P::Le => NodeKind::NdOl,
+            P::Gt => NodeKind::NdMt,
+            P::Ge => NodeKind::NdOm,
+            P::EqEq => NodeKind::NdEq,
+            _ => NodeKind::NdNe,
+        };
+        Ok(Node::new_binary(kind, lhs, rhs, span))
+    }
+
+    // + and - take two numbers, or a pointer and an integer, and - also
+    // takes two pointers.
+    fn check_arith_operands(lhs: &Node, rhs: &Node, op: P, span: Span) -> Result<(), CompileError> {
+        let (lhs_ty, rhs_ty) = (lhs.ty.as_ref().unwrap(), rhs.ty.as_ref().unwrap());
+        let valid = match (lhs_ty.is_pointer(), rhs_ty.is_pointer()) {
+            (false, false) => lhs_ty.is_numeric() && rhs_ty.is_numeric(),
+            (true, false) => rhs_ty.is_integer(),
+            (false, true) => op == P::Plus && lhs_ty.is_integer(),
+            (true, true) => op == P::Minus,
+        };
+        if !valid {
+            return Err(CompileError::new(
+                ErrorKind::TypeMismatch,
+                span,
+                format!("invalid operands to binary {}", op.as_str()),
+            ));
+        }
+        Ok(())
+    }
+
+    // Checks the type of a condition, or of an operand of ! && ||, which must
+    // be a scalar.
+    fn check_scalar(&mut self) -> Result<(), CompileError> {
+        self.check_type();
+        if self.ty.as_ref().unwrap().is_struct() {
+            return Err(CompileError::new(
+                ErrorKind::TypeMismatch,
+                self.span,
+                "used struct type value where scalar is required".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // Converts both operands of a binary operator to their common type, and
+    // returns that type.
+    fn usual_arith_conv(&mut self) -> Type {
+        let lhs = self.lhs.take().unwrap();
+        let rhs = self.rhs.take().unwrap();
+        let ty = Type::common_type(lhs.ty.as_ref().unwrap(), rhs.ty.as_ref().unwrap());
+
+        self.lhs = Some(Box::new(Node::new_cast(lhs, ty.clone())));
+        self.rhs = Some(Box::new(Node::new_cast(rhs, ty.clone())));
+        ty
+    }
+
+    fn is_arith_binary(&self) -> bool {
+        self.lhs.as_ref().unwrap().ty.as_ref().unwrap().is_numeric()
+            && self.rhs.as_ref().unwrap().ty.as_ref().unwrap().is_numeric()
     }
 
     fn check_type(&mut self) {
-        if self.ty != None {
+        if self.ty.is_some() {
             return;
         }
 
@@ -162,13 +352,75 @@ impl Node {
             | NodeKind::NdSub
             | NodeKind::NdMul
             | NodeKind::NdDiv
-            | NodeKind::NdAs => {
-                self.ty = self.lhs.clone().unwrap().ty;
-                return;
+            | NodeKind::NdMod
+            | NodeKind::NdBitAnd
+            | NodeKind::NdBitOr
+            | NodeKind::NdBitXor => {
+                if self.is_arith_binary() {
+                    self.ty = Some(Box::new(self.usual_arith_conv()));
+                } else {
+                    self.ty = self.lhs.clone().unwrap().ty;
+                }
+            }
+            NodeKind::NdEq
+            | NodeKind::NdNe
+            | NodeKind::NdLt
+            | NodeKind::NdOl
+            | NodeKind::NdMt
+            | NodeKind::NdOm => {
+                if self.is_arith_binary() {
+                    self.usual_arith_conv();
+                }
+                self.ty = Some(Box::new(Type::new_int()));
+            }
+            // the result of a shift or ~ has the promoted type of its left
+            // operand
+            NodeKind::NdShl | NodeKind::NdShr | NodeKind::NdBitNot => {
+                let lhs = self.lhs.take().unwrap();
+                let ty = Type::common_type(lhs.ty.as_ref().unwrap(), lhs.ty.as_ref().unwrap());
+                self.lhs = Some(Box::new(Node::new_cast(lhs, ty.clone())));
+                self.ty = Some(Box::new(ty));
+            }
+            NodeKind::NdLogAnd | NodeKind::NdLogOr | NodeKind::NdNot => {
+                self.ty = Some(Box::new(Type::new_int()));
+            }
+            NodeKind::NdComma => {
+                self.ty = self.rhs.as_ref().unwrap().ty.clone();
+            }
+            NodeKind::NdCond => {
+                let mut then = self.then.take().unwrap();
+                let mut els = self.els.take().unwrap();
+                then.check_type();
+                els.check_type();
+                let (then_ty, els_ty) = (then.ty.clone().unwrap(), els.ty.clone().unwrap());
+
+                let ty = if then_ty.is_numeric() && els_ty.is_numeric() {
+                    let ty = Type::common_type(&then_ty, &els_ty);
+                    then = Box::new(Node::new_cast(then, ty.clone()));
+                    els = Box::new(Node::new_cast(els, ty.clone()));
+                    ty
+                } else if then_ty.kind == TypeKind::TyArr {
+                    then_ty.ptr_to.unwrap().pointer_to()
+                } else {
+                    *then_ty
+                };
+                self.then = Some(then);
+                self.els = Some(els);
+                self.ty = Some(Box::new(ty));
+            }
+            NodeKind::NdAs => {
+                let ty = *self.lhs.clone().unwrap().ty.unwrap();
+                if ty.is_numeric() {
+                    let rhs = self.rhs.take().unwrap();
+                    self.rhs = Some(Box::new(Node::new_cast(rhs, ty.clone())));
+                }
+                self.ty = Some(Box::new(ty));
             }
             NodeKind::NdLv => {
                 self.ty = Some(Box::new(self.lvar.clone().unwrap().ty));
-                return;
+            }
+            NodeKind::NdGv => {
+                self.ty = Some(Box::new(self.gvar.clone().unwrap().ty));
             }
             NodeKind::NdAddr => {
                 if self.lhs.clone().unwrap().ty.unwrap().kind == TypeKind::TyArr {
@@ -179,15 +431,12 @@ impl Node {
                 } else {
                     self.ty = Some(Box::new(self.lhs.clone().unwrap().ty.unwrap().pointer_to()));
                 }
-                return;
             }
             NodeKind::NdDeref => {
                 self.ty = Some(self.lhs.clone().unwrap().ty.unwrap().ptr_to.unwrap());
-                return;
             }
             NodeKind::NdFunc | NodeKind::NdNum => {
                 self.ty = Some(Box::new(Type::new_int()));
-                return;
             }
             _ => {}
         }
@@ -203,391 +452,2519 @@ pub struct LVar {
 
 impl LVar {
     fn new_lvar(id: usize, ty: Type, name: String) -> Self {
-        Self {
-            id: id,
-            ty: ty,
-            name: name,
-        }
+        Self { id, ty, name }
     }
 }
 
-pub struct Function {
-    pub ty: TypeKind,
+#[derive(Clone, PartialEq)]
+pub struct GVar {
     pub name: String,
-    pub paramnum: usize,
-    pub locals: Vec<LVar>,
-    pub body: Vec<Node>,
+    pub ty: Type,
 }
 
-pub struct Parser<'a> {
-    tokens: &'a Vec<Token>,
-    pos: usize,
-    temp_locals: Vec<LVar>,
-    pub functions: Vec<Function>,
+// What a name in the variable namespace refers to.
+#[derive(Clone)]
+enum VarScope {
+    Local(LVar),
+    Global(GVar),
+    Typedef(Type),
+    EnumConst(i64),
 }
 
-impl<'a> Parser<'a> {
-    fn find_lvar(&mut self, name: String) -> LVar {
-        for local in &self.temp_locals {
-            if local.name == name {
-                return local.clone();
-            }
-        }
-
-        eprintln!("Does not match any local variable");
-        process::exit(1);
-    }
-
-    fn funcargs(&mut self) -> Vec<Node> {
-        let mut args = vec![];
-        if self.consume(")") {
-            return args;
-        }
+// Storage-class specifiers given along with the type of a declaration.
+#[derive(Default)]
+struct VarAttr {
+    is_typedef: bool,
+    is_extern: bool,
+    is_static: bool,
+}
 
-        args.push(self.add());
-        while self.consume(",") {
-            args.push(self.add());
-        }
-        self.expect(")");
+// A block scope. A name is looked up from the innermost scope outwards, so
+// that an inner declaration shadows an outer one.
+#[derive(Default)]
+struct Scope {
+    vars: HashMap<String, VarScope>,
+    tags: HashMap<String, Type>, // struct, union and enum tags, in a namespace of their own
+}
 
-        args
-    }
+// A switch statement being parsed, collecting the labels of its body.
+struct Switch {
+    ty: Type, // the promoted type of the controlling expression
+    cases: Vec<i64>,
+    has_default: bool,
+}
 
-    // primary = '(' expr ')' | ident ("(" (args)* ")") | num
-    fn primary(&mut self) -> Node {
-        if self.consume("(") {
-            let node = self.expr();
-            self.expect(")");
-            return node;
-        }
+// A pointer-sized slot in a global's initial data that holds the address
+// of label plus addend, to be filled in by the assembler.
+pub struct Reloc {
+    pub offset: usize,
+    pub label: String,
+    pub addend: i64,
+}
 
-        if self.tokens[self.pos].kind == TokenKind::TkIdent {
-            let name = &self.tokens[self.pos].op;
+pub struct Global {
+    pub gvar: GVar,
+    pub data: Option<Vec<u8>>, // None for a global without initializer, placed in .bss
+    pub relocs: Vec<Reloc>,
+    pub is_static: bool, // not visible outside the file
+}
 
-            self.pos += 1;
-            if self.consume("(") {
-                let node = Node {
-                    kind: NodeKind::NdFunc,
-                    funcname: name.to_string(),
-                    args: self.funcargs(),
-                    ..Default::default()
-                };
+// The initializer of a variable, shaped after its type: an array has one
+// child per element and a struct one per member, and a scalar has the
+// expression it is initialized with, or none if it is implicitly zero. A
+// struct initialized from another struct also has an expression.
+struct Initializer {
+    ty: Type,
+    expr: Option<Box<Node>>,
+    children: Vec<Initializer>,
+}
 
-                return node;
-            } else {
-                let lvar = self.find_lvar(name.to_string());
-                return Node::new_node_lv(Box::new(lvar));
+impl Initializer {
+    fn new(ty: &Type) -> Self {
+        let mut init = Initializer {
+            ty: ty.clone(),
+            expr: None,
+            children: vec![],
+        };
+        if ty.kind == TypeKind::TyArr {
+            for _ in 0..ty.size_array {
+                init.children
+                    .push(Initializer::new(ty.ptr_to.as_ref().unwrap()));
             }
         }
+        for member in &ty.members {
+            init.children.push(Initializer::new(&member.ty));
+        }
+        init
+    }
 
-        self.pos += 1;
-        Node::new_node_num(self.tokens[self.pos - 1].val)
+    // Only the first member of a union is initialized.
+    fn initialized_members(&self) -> usize {
+        match self.ty.kind {
+            TypeKind::TyUnion => self.children.len().min(1),
+            _ => self.children.len(),
+        }
+    }
+
+    // Returns the i-th element of an array initializer. An incomplete array
+    // grows to hold it.
+    fn child(&mut self, i: usize) -> Option<&mut Initializer> {
+        if self.ty.kind == TypeKind::TyArr && self.ty.incomplete && i == self.children.len() {
+            let elem = Initializer::new(self.ty.ptr_to.as_ref().unwrap());
+            self.children.push(elem);
+        }
+        self.children.get_mut(i)
     }
 
-    // postfix = primary ("[" expr "]")*
-    fn postfix(&mut self) -> Node {
-        let mut node = self.primary();
+    // The type of the variable, with the length of an incomplete array taken
+    // from the number of elements in the initializer.
+    fn complete_type(&self) -> Type {
+        if self.ty.kind == TypeKind::TyArr && self.ty.incomplete {
+            let elem = *self.ty.ptr_to.clone().unwrap();
+            return elem.array_of(self.children.len());
+        }
+        self.ty.clone()
+    }
+}
 
-        while self.consume("[") {
-            let idx = self.expr();
-            self.expect("]");
-            node = Node::new_unary(
+// Appends an assignment to stmts for every scalar in the initializer of a
+// local variable, so that elements without an initializer are set to zero.
+fn lvar_initializer(
+    init: &Initializer,
+    lhs: Node,
+    stmts: &mut Vec<Node>,
+) -> Result<(), CompileError> {
+    let span = lhs.span;
+    if init.ty.kind == TypeKind::TyArr {
+        for (i, child) in init.children.iter().enumerate() {
+            let idx = Node::new_node_num(i as u64, span);
+            let elem = Node::new_unary(
                 NodeKind::NdDeref,
-                Box::new(Node::new_add(Box::new(node), Box::new(idx))),
+                Box::new(Node::new_add(Box::new(lhs.clone()), Box::new(idx), span)?),
+                span,
             );
+            lvar_initializer(child, elem, stmts)?;
+        }
+        return Ok(());
+    }
+
+    if init.ty.is_struct() && init.expr.is_none() {
+        let members = init.ty.members.iter().zip(&init.children);
+        for (member, child) in members.take(init.initialized_members()) {
+            let lhs = Box::new(lhs.clone());
+            let elem = Node::new_member(lhs, member.clone(), member.ty.clone(), span);
+            lvar_initializer(child, elem, stmts)?;
         }
+        return Ok(());
+    }
+
+    let rhs = match &init.expr {
+        Some(expr) => expr.clone(),
+        None => Box::new(Node::new_node_num(0, span)),
+    };
+    let mut assign = Node::new_binary(NodeKind::NdAs, Box::new(lhs), rhs, span);
+    assign.check_type();
+    stmts.push(Node::new_unary(
+        NodeKind::NdExprStmt,
+        Box::new(assign),
+        span,
+    ));
+    Ok(())
+}
 
-        node
+// Evaluates a constant expression in a static initializer. If label is
+// given, the expression may also be the address of a global plus or minus
+// a constant, in which case the global is stored to label and the constant
+// is returned.
+fn eval(node: &Node, label: Option<&mut Option<String>>) -> Result<i64, CompileError> {
+    let not_constant = || {
+        CompileError::new(
+            ErrorKind::NotConstant,
+            node.span,
+            "initializer element is not constant".to_string(),
+        )
+    };
+    let lhs = || node.lhs.as_deref().unwrap();
+    let rhs = || node.rhs.as_deref().unwrap();
+    // results wrap around to the type of the node
+    let wrap = |val: i64| wrap_to_type(val, node.ty.as_deref());
+    // operands of an unsigned type are divided and compared as u64
+    let unsigned = node
+        .lhs
+        .as_ref()
+        .is_some_and(|lhs| lhs.ty.as_ref().is_some_and(|ty| ty.is_unsigned));
+
+    // a floating-point value converts to an integer by truncation
+    if is_flonum(node) {
+        return Ok(eval_double(node)? as i64);
+    }
+    if node.kind != NodeKind::NdCast && node.lhs.is_some() && is_flonum(lhs()) {
+        let (lhs, rhs) = (eval_double(lhs())?, eval_double(rhs())?);
+        return match node.kind {
+            NodeKind::NdEq => Ok((lhs == rhs) as i64),
+            NodeKind::NdNe => Ok((lhs != rhs) as i64),
+            NodeKind::NdLt => Ok((lhs < rhs) as i64),
+            NodeKind::NdOl => Ok((lhs <= rhs) as i64),
+            NodeKind::NdMt => Ok((lhs > rhs) as i64),
+            NodeKind::NdOm => Ok((lhs >= rhs) as i64),
+            _ => Err(not_constant()),
+        };
     }
 
-    // unary = ( '+' | '-' )? primary | ('&' | '*'| "sizeof") unary | postfix
-    fn unary(&mut self) -> Node {
-        if self.consume("+") {
-            return self.unary();
+    match node.kind {
+        NodeKind::NdNum => return Ok(node.int_val()),
+        NodeKind::NdAdd => return Ok(wrap(eval(lhs(), label)?.wrapping_add(eval(rhs(), None)?))),
+        NodeKind::NdSub => return Ok(wrap(eval(lhs(), label)?.wrapping_sub(eval(rhs(), None)?))),
+        NodeKind::NdMul => return Ok(wrap(eval(lhs(), None)?.wrapping_mul(eval(rhs(), None)?))),
+        NodeKind::NdDiv | NodeKind::NdMod => {
+            let divisor = eval(rhs(), None)?;
+            if divisor == 0 {
+                return Err(not_constant());
+            }
+            let (l, r) = (eval(lhs(), None)?, divisor);
+            return Ok(wrap(match (node.kind == NodeKind::NdDiv, unsigned) {
+                (true, true) => ((l as u64) / (r as u64)) as i64,
+                (true, false) => l.wrapping_div(r),
+                (false, true) => ((l as u64) % (r as u64)) as i64,
+                (false, false) => l.wrapping_rem(r),
+            }));
         }
-        if self.consume("-") {
-            return Node::new_binary(
-                NodeKind::NdSub,
-                Box::new(Node::new_node_num(0)),
-                Box::new(self.unary()),
-            );
+        NodeKind::NdBitAnd => return Ok(eval(lhs(), None)? & eval(rhs(), None)?),
+        NodeKind::NdBitOr => return Ok(eval(lhs(), None)? | eval(rhs(), None)?),
+        NodeKind::NdBitXor => return Ok(eval(lhs(), None)? ^ eval(rhs(), None)?),
+        NodeKind::NdBitNot => return Ok(wrap(!eval(lhs(), None)?)),
+        NodeKind::NdShl => {
+            return Ok(wrap(
+                eval(lhs(), None)?.wrapping_shl(eval(rhs(), None)? as u32),
+            ))
         }
-        if self.consume("&") {
-            return Node::new_unary(NodeKind::NdAddr, Box::new(self.unary()));
+        NodeKind::NdShr => {
+            let (val, shift) = (eval(lhs(), None)?, eval(rhs(), None)? as u32);
+            let ty = node.ty.as_ref().unwrap();
+            return Ok(match (ty.size, ty.is_unsigned) {
+                (8, true) => (val as u64).wrapping_shr(shift) as i64,
+                (_, true) => (val as u32).wrapping_shr(shift) as i64,
+                _ => val.wrapping_shr(shift),
+            });
         }
-        if self.consume("*") {
-            return Node::new_unary(NodeKind::NdDeref, Box::new(self.unary()));
+        NodeKind::NdLogAnd => {
+            return Ok((eval(lhs(), None)? != 0 && eval(rhs(), None)? != 0) as i64)
         }
-        if self.consume("sizeof") {
-            let mut node = self.unary();
-            node.check_type();
-            return Node::new_node_num(node.ty.as_ref().unwrap().size as u32);
+        NodeKind::NdLogOr => return Ok((eval(lhs(), None)? != 0 || eval(rhs(), None)? != 0) as i64),
+        NodeKind::NdNot => return Ok((eval(lhs(), None)? == 0) as i64),
+        NodeKind::NdCond => {
+            let cond = eval(node.cond.as_deref().unwrap(), None)?;
+            let branch = if cond != 0 { &node.then } else { &node.els };
+            return eval(branch.as_deref().unwrap(), label);
         }
-
-        self.postfix()
+        NodeKind::NdComma => return eval(rhs(), label),
+        NodeKind::NdEq => return Ok((eval(lhs(), None)? == eval(rhs(), None)?) as i64),
+        NodeKind::NdNe => return Ok((eval(lhs(), None)? != eval(rhs(), None)?) as i64),
+        NodeKind::NdLt | NodeKind::NdOl | NodeKind::NdMt | NodeKind::NdOm => {
+            let (l, r) = (eval(lhs(), None)?, eval(rhs(), None)?);
+            let ord = if unsigned {
+                (l as u64).cmp(&(r as u64))
+            } else {
+                l.cmp(&r)
+            };
+            return Ok(match node.kind {
+                NodeKind::NdLt => ord == Ordering::Less,
+                NodeKind::NdOl => ord != Ordering::Greater,
+                NodeKind::NdMt => ord == Ordering::Greater,
+                _ => ord != Ordering::Less,
+            } as i64);
+        }
+        _ => {}
     }
 
-    // mul = unary ( '*' unary | '/' unary )*
-    fn mul(&mut self) -> Node {
-        let mut lhs = self.unary();
+    if node.kind == NodeKind::NdCast {
+        // a floating-point value of 2^63 or more fits only in a u64
+        if is_flonum(lhs()) && node.ty.as_ref().is_some_and(|ty| ty.is_unsigned) {
+            return Ok(wrap(eval_double(lhs())? as u64 as i64));
+        }
+        return Ok(wrap(eval(lhs(), label)?));
+    }
 
-        loop {
-            if self.consume("*") {
-                lhs = Node::new_binary(NodeKind::NdMul, Box::new(lhs), Box::new(self.unary()));
-            } else if self.consume("/") {
-                lhs = Node::new_binary(NodeKind::NdDiv, Box::new(lhs), Box::new(self.unary()));
-            } else {
-                break;
-            }
+    let label = label.ok_or_else(not_constant)?;
+    match node.kind {
+        NodeKind::NdAddr => eval_addr(lhs(), label).ok_or_else(not_constant),
+        // an array decays to the address of its first element
+        NodeKind::NdGv | NodeKind::NdStr if node.ty.as_ref().unwrap().kind == TypeKind::TyArr => {
+            eval_addr(node, label).ok_or_else(not_constant)
         }
+        _ => Err(not_constant()),
+    }
+}
 
-        lhs
+// Converts an integer value to ty, as a cast does: it is truncated to the
+// size of ty and extended back to 64 bits by its signedness.
+fn wrap_to_type(val: i64, ty: Option<&Type>) -> i64 {
+    let ty = match ty {
+        Some(ty) if ty.is_integer() && ty.size != 8 => ty,
+        _ => return val,
+    };
+    match (ty.size, ty.is_unsigned) {
+        _ if ty.kind == TypeKind::TyBool => (val != 0) as i64,
+        (1, false) => val as i8 as i64,
+        (1, true) => val as u8 as i64,
+        (2, false) => val as i16 as i64,
+        (2, true) => val as u16 as i64,
+        (_, false) => val as i32 as i64,
+        (_, true) => val as u32 as i64,
     }
+}
 
-    // add = mul ( "+" mul | "-" mul )*
-    fn add(&mut self) -> Node {
-        let mut lhs = self.mul();
+// Structs are neither passed nor returned by value.
+fn check_return_type(ty: &Type, span: Span) -> Result<(), CompileError> {
+    if ty.is_struct() {
+        return Err(CompileError::new(
+            ErrorKind::TypeMismatch,
+            span,
+            "returning a struct by value is not supported; return a pointer to it".to_string(),
+        ));
+    }
+    Ok(())
+}
 
-        loop {
-            if self.consume("+") {
-                lhs = Node::new_add(Box::new(lhs), Box::new(self.mul()));
-            } else if self.consume("-") {
-                lhs = Node::new_sub(Box::new(lhs), Box::new(self.mul()));
-            } else {
-                break;
-            }
-        }
+// Builds *tmp for a temporary holding an address.
+fn deref_lvar(tmp: &LVar, span: Span) -> Node {
+    Node::new_unary(
+        NodeKind::NdDeref,
+        Box::new(Node::new_node_lv(Box::new(tmp.clone()), span)),
+        span,
+    )
+}
+
+fn is_flonum(node: &Node) -> bool {
+    node.ty.as_ref().is_some_and(|ty| ty.is_flonum())
+}
 
-        lhs
+// Evaluates a constant expression as a double. An expression of type float
+// is rounded to float precision.
+fn eval_double(node: &Node) -> Result<f64, CompileError> {
+    let ty = node.ty.as_deref().cloned().unwrap_or_else(Type::new_int);
+    if !ty.is_flonum() {
+        let val = eval(node, None)?;
+        if ty.is_unsigned && ty.size == 8 {
+            return Ok(val as u64 as f64);
+        }
+        return Ok(val as f64);
     }
 
-    // relational = add ( ">" add | "<" add | ">=" add | "<=" add )*
-    fn relational(&mut self) -> Node {
-        let mut lhs = self.add();
+    let lhs = || eval_double(node.lhs.as_deref().unwrap());
+    let rhs = || eval_double(node.rhs.as_deref().unwrap());
+    let val = match node.kind {
+        NodeKind::NdNum => node.fval,
+        NodeKind::NdAdd => lhs()? + rhs()?,
+        NodeKind::NdSub => lhs()? - rhs()?,
+        NodeKind::NdMul => lhs()? * rhs()?,
+        NodeKind::NdDiv => lhs()? / rhs()?,
+        NodeKind::NdCast => lhs()?,
+        _ => {
+            return Err(CompileError::new(
+                ErrorKind::NotConstant,
+                node.span,
+                "initializer element is not constant".to_string(),
+            ))
+        }
+    };
 
-        loop {
-            if self.consume(">") {
-                lhs = Node::new_binary(NodeKind::NdMt, Box::new(lhs), Box::new(self.add()));
-            } else if self.consume("<") {
-                lhs = Node::new_binary(NodeKind::NdLt, Box::new(lhs), Box::new(self.add()));
-            } else if self.consume(">=") {
-                lhs = Node::new_binary(NodeKind::NdOm, Box::new(lhs), Box::new(self.add()));
-            } else if self.consume("<=") {
-                lhs = Node::new_binary(NodeKind::NdOl, Box::new(lhs), Box::new(self.add()));
-            } else {
-                break;
-            }
+    if ty.kind == TypeKind::TyFloat {
+        return Ok(val as f32 as f64);
+    }
+    Ok(val)
+}
+
+// Evaluates the address of a global lvalue, as label plus the returned offset.
+fn eval_addr(node: &Node, label: &mut Option<String>) -> Option<i64> {
+    match node.kind {
+        NodeKind::NdGv => {
+            *label = Some(node.gvar.as_ref().unwrap().name.clone());
+            Some(0)
         }
+        NodeKind::NdStr => {
+            *label = Some(format!(".L.str.{}", node.val));
+            Some(0)
+        }
+        NodeKind::NdDeref => eval(node.lhs.as_deref().unwrap(), Some(label)).ok(),
+        _ => None,
+    }
+}
+
+pub struct Function {
+    pub ty: Type, // the function's type, with its return and parameter types
+    pub name: String,
+    pub paramnum: usize,
+    pub locals: Vec<LVar>,
+    pub va_area: Option<LVar>, // register save area of a variadic function
+    pub body: Vec<Node>,
+    pub is_static: bool, // not visible outside the file
+}
+
+// The compound assignment operators, with the operator each applies.
+static COMPOUND_ASSIGNS: [(P, P); 10] = [
+    (P::AddAssign, P::Plus),
+    (P::SubAssign, P::Minus),
+    (P::MulAssign, P::Star),
+    (P::DivAssign, P::Slash),
+    (P::ModAssign, P::Percent),
+    (P::AndAssign, P::Amp),
+    (P::OrAssign, P::Pipe),
+    (P::XorAssign, P::Caret),
+    (P::ShlAssign, P::Shl),
+    (P::ShrAssign, P::Shr),
+];
+
+// Size of the register save area: six general-purpose registers, then
+// eight vector registers of 16 bytes each.
+const VA_AREA_SIZE: usize = 6 * 8 + 8 * 16;
+
+// Each type specifier counts in its own digits, so that the sum tells which
+// specifiers were given and how many times.
+const BOOL: u32 = 1;
+const CHAR: u32 = 1 << 2;
+const SHORT: u32 = 1 << 4;
+const INT: u32 = 1 << 6;
+const LONG: u32 = 1 << 8;
+const SIGNED: u32 = 1 << 10;
+const UNSIGNED: u32 = 1 << 12;
+const OTHER: u32 = 1 << 14; // a struct or union, which combines with no other specifier
+const VOID: u32 = 1 << 16;
+const FLOAT: u32 = 1 << 18;
+const DOUBLE: u32 = 1 << 20;
+
+const SIGNED_CHAR: u32 = SIGNED + CHAR;
+const UNSIGNED_CHAR: u32 = UNSIGNED + CHAR;
+const SHORT_INT: u32 = SHORT + INT;
+const SIGNED_SHORT: u32 = SIGNED + SHORT;
+const SIGNED_SHORT_INT: u32 = SIGNED + SHORT + INT;
+const UNSIGNED_SHORT: u32 = UNSIGNED + SHORT;
+const UNSIGNED_SHORT_INT: u32 = UNSIGNED + SHORT + INT;
+const SIGNED_INT: u32 = SIGNED + INT;
+const UNSIGNED_INT: u32 = UNSIGNED + INT;
+const LONG_INT: u32 = LONG + INT;
+const LONG_LONG: u32 = LONG + LONG;
+const LONG_LONG_INT: u32 = LONG + LONG + INT;
+const SIGNED_LONG: u32 = SIGNED + LONG;
+const SIGNED_LONG_INT: u32 = SIGNED + LONG + INT;
+const SIGNED_LONG_LONG: u32 = SIGNED + LONG + LONG;
+const SIGNED_LONG_LONG_INT: u32 = SIGNED + LONG + LONG + INT;
+const UNSIGNED_LONG: u32 = UNSIGNED + LONG;
+const UNSIGNED_LONG_INT: u32 = UNSIGNED + LONG + INT;
+const UNSIGNED_LONG_LONG: u32 = UNSIGNED + LONG + LONG;
+const UNSIGNED_LONG_LONG_INT: u32 = UNSIGNED + LONG + LONG + INT;
+
+static QUALIFIERS: [Kw; 3] = [Kw::Const, Kw::Volatile, Kw::Restrict];
+
+// Function and storage-class specifiers that change nothing in the generated
+// code, and are skipped like the qualifiers.
+static IGNORED_SPECIFIERS: [Kw; 4] = [Kw::Inline, Kw::Noreturn, Kw::Register, Kw::Auto];
+
+static TYPE_SPECIFIERS: [(Kw, u32); 10] = [
+    (Kw::Void, VOID),
+    (Kw::Bool, BOOL),
+    (Kw::Char, CHAR),
+    (Kw::Short, SHORT),
+    (Kw::Int, INT),
+    (Kw::Long, LONG),
+    (Kw::Float, FLOAT),
+    (Kw::Double, DOUBLE),
+    (Kw::Signed, SIGNED),
+    (Kw::Unsigned, UNSIGNED),
+];
+
+pub struct Parser<'a> {
+    tokens: &'a Vec<Token>,
+    pos: usize,
+    temp_locals: Vec<LVar>,     // locals of the function being parsed
+    scopes: Vec<Scope>,         // the file scope, then the enclosing blocks
+    func_ty: Type,              // type of the function being parsed
+    va_area: Option<LVar>,      // register save area of the function being parsed
+    loop_depth: usize,          // number of loops enclosing the statement being parsed
+    switches: Vec<Switch>,      // switches enclosing the statement being parsed
+    labels: Vec<String>,        // labels defined so far in the function being parsed
+    gotos: Vec<(String, Span)>, // goto targets in the function being parsed
+    pub functions: Vec<Function>,
+    pub globals: Vec<Global>,
+    pub strings: Vec<Vec<u8>>, // contents of string literals, by NdStr val
+    pub diagnostics: Vec<CompileError>,
+    pub max_errors: usize, // 0 means no limit
+}
 
-        lhs
+impl<'a> Parser<'a> {
+    fn enter_scope(&mut self) {
+        self.scopes.push(Scope::default());
     }
 
-    // equality = relational ( "==" relational | "!=" relational )*
-    fn equality(&mut self) -> Node {
-        let mut lhs = self.relational();
+    fn leave_scope(&mut self) {
+        self.scopes.pop();
+    }
 
-        loop {
-            if self.consume("==") {
-                lhs = Node::new_binary(NodeKind::NdEq, Box::new(lhs), Box::new(self.mul()));
-            } else if self.consume("!=") {
-                lhs = Node::new_binary(NodeKind::NdNe, Box::new(lhs), Box::new(self.mul()));
-            } else {
-                break;
-            }
-        }
+    fn find_var(&self, name: &str) -> Option<&VarScope> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.vars.get(name))
+    }
 
-        lhs
+    fn find_tag(&self, tag: &str) -> Option<&Type> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.tags.get(tag))
     }
 
-    // assign = equality ( "=" assign )?
-    fn assign(&mut self) -> Node {
-        let mut lhs = self.equality();
+    fn push_tag(&mut self, tag: String, ty: Type) {
+        self.scopes.last_mut().unwrap().tags.insert(tag, ty);
+    }
 
-        if self.consume("=") {
-            lhs = Node::new_binary(NodeKind::NdAs, Box::new(lhs), Box::new(self.assign()));
+    // Types are copied by value, so a pointer to a struct taken before the
+    // struct was defined, as in a member "struct node *next", still points
+    // to the incomplete struct. Its members are found by its tag.
+    fn complete_struct(&self, ty: &Type) -> Type {
+        if ty.is_struct() && ty.incomplete {
+            if let Some(found) = self.find_tag(ty.tag.as_ref().unwrap()) {
+                return found.clone();
+            }
         }
-
-        lhs
+        ty.clone()
     }
 
-    // expr = assign
-    fn expr(&mut self) -> Node {
-        return self.assign();
+    // Checks that a value of type from may be converted to type to as if by
+    // assignment. A struct converts only to the same struct type, and a
+    // pointer never converts to or from a floating-point type.
+    fn check_assignable(
+        &self,
+        to: &Type,
+        from: &Type,
+        what: &str,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        let compatible = if to.is_struct() || from.is_struct() {
+            self.complete_struct(to) == self.complete_struct(from)
+        } else {
+            !(to.is_pointer() && from.is_flonum() || to.is_flonum() && from.is_pointer())
+        };
+        if !compatible {
+            return Err(CompileError::new(
+                ErrorKind::TypeMismatch,
+                span,
+                format!("incompatible types when {}", what),
+            ));
+        }
+        Ok(())
     }
 
-    // declaration = basetype ident ("[" num "]")?
-    fn declaration(&mut self) -> Node {
-        let base = self.basetype();
-        let name = self.expect_ident();
-        let ty = self.type_suffix(base);
+    // Builds the access to member name of a struct or union.
+    fn struct_ref(&mut self, mut lhs: Node, name: &str, span: Span) -> Result<Node, CompileError> {
+        lhs.check_type();
+        let ty = self.complete_struct(lhs.ty.as_ref().unwrap());
+        if !ty.is_struct() {
+            return Err(CompileError::new(
+                ErrorKind::TypeMismatch,
+                span,
+                format!(
+                    "request for member '{}' in something not a structure or union",
+                    name
+                ),
+            ));
+        }
+        let member = match ty.find_member(name) {
+            Some(member) => member.clone(),
+            None => {
+                return Err(CompileError::new(
+                    ErrorKind::TypeMismatch,
+                    span,
+                    format!("no member named '{}'", name),
+                ))
+            }
+        };
+
+        let ty = self.complete_struct(&member.ty);
+        Ok(Node::new_member(Box::new(lhs), member, ty, span))
+    }
 
-        let lvar = LVar::new_lvar(self.temp_locals.len(), ty, name);
+    // Declares a local variable in the innermost scope.
+    fn new_lvar(&mut self, ty: Type, name: String, span: Span) -> Result<LVar, CompileError> {
+        if self.scopes.last().unwrap().vars.contains_key(&name) {
+            self.recover(CompileError::new(
+                ErrorKind::Redeclaration,
+                span,
+                format!("redeclaration of '{}'", name),
+            ))?;
+        }
 
+        let lvar = LVar::new_lvar(self.temp_locals.len(), ty, name.clone());
         self.temp_locals.push(lvar.clone());
-        let mut node = Node::new_node_lv(Box::new(lvar));
-        node.check_type();
+        let scope = self.scopes.last_mut().unwrap();
+        scope.vars.insert(name, VarScope::Local(lvar.clone()));
 
-        return node;
+        Ok(lvar)
     }
 
-    // stmt = "return" expr ";"
-    //        | "{" stmt* "}"
-    //        | "if" "(" cond ")" stmt ( "else" stmt )?
-    //        | "while" "(" cond ")" stmt
-    //        | "for" "(" expr? ";" expr? ";" expr? ")" stmt
-    //        | declaration ";"
-    //        | expr ";"
-    fn stmt(&mut self) -> Node {
-        let mut node;
-
-        if self.consume("{") {
-            node = Node::new_node(NodeKind::NdBlock);
-            node.blocks = vec![];
-            while !self.consume("}") {
-                let mut block = self.stmt();
-                block.check_type();
-                node.blocks.push(block);
+    // Adds a global to the file scope. A global may be declared more than
+    // once with the same type, as long as only one declaration initializes it.
+    //
+    // An extern declaration only makes the name known; the global is defined
+    // in another file unless it is also declared without extern.
+    fn declare_global(
+        &mut self,
+        global: Global,
+        is_extern: bool,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        let name = global.gvar.name.clone();
+        let name = &name;
+        let ty = &global.gvar.ty;
+        if let Some(VarScope::Global(prev)) = self.scopes[0].vars.get(name) {
+            // an array may be declared without its length first
+            let completes = |prev: &Type, ty: &Type| {
+                prev.kind == TypeKind::TyArr && prev.incomplete && prev.ptr_to == ty.ptr_to
+            };
+            if &prev.ty != ty && !completes(&prev.ty, ty) && !completes(ty, &prev.ty) {
+                return Err(CompileError::new(
+                    ErrorKind::Redeclaration,
+                    span,
+                    format!("conflicting types for '{}'", name),
+                ));
             }
-            return node;
+            if ty.incomplete {
+                return Ok(());
+            }
+        }
+        let scope = &mut self.scopes[0];
+        scope
+            .vars
+            .insert(name.clone(), VarScope::Global(global.gvar.clone()));
+        if is_extern {
+            return Ok(());
         }
 
-        if self.consume("return") {
-            node = Node::new_unary(NodeKind::NdRt, Box::new(self.expr()));
-        } else if self.consume("if") {
-            node = Node::new_node(NodeKind::NdIf);
-            self.expect("(");
-            let mut cond = self.expr();
-            cond.check_type();
-            node.cond = Some(Box::new(cond));
-            self.expect(")");
-            let mut then = self.stmt();
-            then.check_type();
-            node.then = Some(Box::new(then));
-            if self.consume("else") {
-                let mut els = self.stmt();
-                els.check_type();
-                node.els = Some(Box::new(els));
+        if let Some(prev) = self.globals.iter_mut().find(|prev| &prev.gvar.name == name) {
+            prev.gvar = global.gvar;
+            if global.data.is_some() {
+                if prev.data.is_some() {
+                    return Err(CompileError::new(
+                        ErrorKind::Redeclaration,
+                        span,
+                        format!("redefinition of '{}'", name),
+                    ));
+                }
+                prev.data = global.data;
+                prev.relocs = global.relocs;
             }
+            return Ok(());
+        }
+        self.globals.push(global);
 
-            return node;
-        } else if self.consume("while") {
-            node = Node::new_node(NodeKind::NdWhile);
-            self.expect("(");
-            let mut cond = self.expr();
-            cond.check_type();
-            node.cond = Some(Box::new(cond));
-            self.expect(")");
-            let mut then = self.stmt();
-            then.check_type();
-            node.then = Some(Box::new(then));
+        Ok(())
+    }
 
-            return node;
-        } else if self.consume("for") {
-            node = Node::new_node(NodeKind::NdFor);
-            self.expect("(");
-            if self.tokens[self.pos].op != ";" {
-                let mut preop = self.expr();
-                preop.check_type();
-                node.preop = Some(Box::new(preop));
-            }
-            self.expect(";");
-            if self.tokens[self.pos].op != ";" {
-                let mut cond = self.expr();
-                cond.check_type();
-                node.cond = Some(Box::new(cond));
+    // Adds a function to the file scope, where its prototype, if any, is
+    // checked against earlier declarations.
+    fn declare_function(&mut self, name: &str, ty: &Type, span: Span) -> Result<(), CompileError> {
+        match self.scopes[0].vars.get(name) {
+            Some(VarScope::Global(prev)) if prev.ty.kind == TypeKind::TyFunc => {
+                if !prev.ty.is_compatible_func(ty) {
+                    return Err(CompileError::new(
+                        ErrorKind::Redeclaration,
+                        span,
+                        format!("conflicting types for '{}'", name),
+                    ));
+                }
+                // keep the prototype of f(int) over a later f()
+                if ty.is_unprototyped() {
+                    return Ok(());
+                }
             }
-            self.expect(";");
-            if self.tokens[self.pos].op != ")" {
-                let mut postop = self.expr();
-                postop.check_type();
-                node.postop = Some(Box::new(postop));
+            Some(_) => {
+                return Err(CompileError::new(
+                    ErrorKind::Redeclaration,
+                    span,
+                    format!("'{}' redeclared as different kind of symbol", name),
+                ))
             }
-            self.expect(")");
-            let mut then = self.stmt();
-            then.check_type();
-            node.then = Some(Box::new(then));
-
-            return node;
-        } else if self.tokens[self.pos].op == "int" {
-            node = self.declaration();
-            node.check_type();
-        } else {
-            node = self.expr();
+            None => {}
         }
 
-        self.expect(";");
-
-        node
+        let gvar = GVar {
+            name: name.to_string(),
+            ty: ty.clone(),
+        };
+        self.scopes[0]
+            .vars
+            .insert(name.to_string(), VarScope::Global(gvar));
+        Ok(())
     }
 
-    // function = type ident "(" (type params)* ")" "{" stmt* "}"
-    fn function(&mut self) -> Function {
-        let ty = self.basetype();
-        let name = self.expect_ident();
-        let mut func = Function {
-            ty: ty.kind,
-            name: name,
-            paramnum: 0,
-            locals: vec![],
-            body: vec![],
+    // Builds a call to the function name. Arguments are converted to the
+    // types of the parameters, as if by assignment. A function that was not
+    // declared is taken to return int and accept any arguments.
+    fn funcall(
+        &mut self,
+        name: &str,
+        mut args: Vec<Node>,
+        span: Span,
+    ) -> Result<Node, CompileError> {
+        let ty = match self.find_var(name) {
+            Some(VarScope::Global(gvar)) if gvar.ty.kind == TypeKind::TyFunc => gvar.ty.clone(),
+            Some(_) => {
+                return Err(CompileError::new(
+                    ErrorKind::TypeMismatch,
+                    span,
+                    format!("called object '{}' is not a function", name),
+                ))
+            }
+            None => Type::func_type(Type::new_int(), vec![], true),
         };
 
-        self.expect("(");
-        if self.consume(")") {
-        } else {
-            let mut ty = self.basetype();
-            let mut name = self.expect_ident();
-
-            let lvar = LVar::new_lvar(self.temp_locals.len(), ty, name);
-            self.temp_locals.push(lvar);
+        if args.len() < ty.params.len() {
+            return Err(CompileError::new(
+                ErrorKind::TypeMismatch,
+                span,
+                format!("too few arguments to function '{}'", name),
+            ));
+        }
+        if args.len() > ty.params.len() && !ty.is_variadic {
+            return Err(CompileError::new(
+                ErrorKind::TypeMismatch,
+                args[ty.params.len()].span,
+                format!("too many arguments to function '{}'", name),
+            ));
+        }
 
-            while self.consume(",") {
-                ty = self.basetype();
-                name = self.expect_ident();
+        for (i, arg) in args.iter_mut().enumerate() {
+            arg.check_type();
+            let arg_ty = arg.ty.as_ref().unwrap();
+            let param = match ty.params.get(i) {
+                Some(param) => param,
+                None => {
+                    // an argument matching "..." is promoted, float to double
+                    if arg_ty.kind == TypeKind::TyFloat {
+                        *arg = Node::new_cast(Box::new(std::mem::take(arg)), Type::new_double());
+                    }
+                    continue;
+                }
+            };
 
-                let lvar = LVar::new_lvar(self.temp_locals.len(), ty, name);
-                self.temp_locals.push(lvar);
+            let compatible = if param.is_numeric() {
+                arg_ty.is_numeric()
+            } else if param.is_pointer() {
+                arg_ty.is_pointer() || (arg.kind == NodeKind::NdNum && arg.val == 0)
+            } else {
+                false
+            };
+            if !compatible {
+                return Err(CompileError::new(
+                    ErrorKind::TypeMismatch,
+                    arg.span,
+                    format!("incompatible type for argument {} of '{}'", i + 1, name),
+                ));
+            }
+            if param.is_numeric() {
+                *arg = Node::new_cast(Box::new(std::mem::take(arg)), param.clone());
             }
-            func.paramnum = self.temp_locals.len();
-            self.expect(")");
         }
-        self.expect("{");
 
-        while self.tokens[self.pos].op != "}" {
-            let mut node = self.stmt();
-            node.check_type();
-            func.body.push(node);
-        }
+        Ok(Node {
+            kind: NodeKind::NdFunc,
+            ty: ty.return_ty.clone(),
+            funcname: name.to_string(),
+            func_ty: Some(Box::new(ty)),
+            args,
+            span,
+            ..Default::default()
+        })
+    }
 
-        func.locals = self.temp_locals.clone();
+    fn funcargs(&mut self) -> Result<Vec<Node>, CompileError> {
+        let mut args = vec![];
+        if self.consume(P::RParen) {
+            return Ok(args);
+        }
 
-        self.pos += 1;
+        args.push(self.assign()?);
+        while self.consume(P::Comma) {
+            args.push(self.assign()?);
+        }
+        self.expect(P::RParen)?;
 
-        func
+        Ok(args)
     }
 
-    // program = function*
-    pub fn program(&mut self) {
-        let mut funcs: Vec<Function> = vec![];
+    // primary = '(' expr ')' | ident ("(" (args)* ")") | str+ | num
+    fn primary(&mut self) -> Result<Node, CompileError> {
+        if self.consume(P::LParen) {
+            let node = self.expr()?;
+            self.expect(P::RParen)?;
+            return Ok(node);
+        }
+
+        let span = self.span();
+        if self.tokens[self.pos].kind == TokenKind::TkIdent {
+            let name = &self.tokens[self.pos].op;
+
+            self.pos += 1;
+            if self.consume(P::LParen) {
+                if ["va_start", "va_arg", "va_end"].contains(&name.as_str()) {
+                    return self.va_builtin(name, span);
+                }
+                let args = self.funcargs()?;
+                return self.funcall(name, args, span);
+            } else {
+                match self.find_var(name) {
+                    Some(VarScope::Local(lvar)) => {
+                        return Ok(Node::new_node_lv(Box::new(lvar.clone()), span));
+                    }
+                    Some(VarScope::Global(gvar)) => {
+                        return Ok(Node::new_node_gv(Box::new(gvar.clone()), span));
+                    }
+                    Some(VarScope::EnumConst(val)) => {
+                        return Ok(Node::new_node_num(*val as u64, span));
+                    }
+                    Some(VarScope::Typedef(_)) => {
+                        return Err(CompileError::new(
+                            ErrorKind::UnexpectedToken,
+                            span,
+                            format!("unexpected type name '{}'", name),
+                        ));
+                    }
+                    None => {}
+                }
+
+                // keep parsing the expression as if the variable were a number
+                self.recover(CompileError::new(
+                    ErrorKind::Undeclared,
+                    span,
+                    format!("'{}' undeclared", name),
+                ))?;
+                return Ok(Node::new_node_num(0, span));
+            }
+        }
+
+        if self.tokens[self.pos].kind == TokenKind::TkStr {
+            let contents = self.string_literal();
+            let node = Node {
+                ty: Some(Box::new(Type::new_char().array_of(contents.len()))),
+                val: self.strings.len() as u64,
+                ..Node::new_node(NodeKind::NdStr, span)
+            };
+            self.strings.push(contents);
+            return Ok(node);
+        }
+
+        if self.tokens[self.pos].kind != TokenKind::TkNum {
+            return Err(CompileError::new(
+                ErrorKind::UnexpectedToken,
+                span,
+                format!("expected expression but got {}", self.current()),
+            ));
+        }
+        self.pos += 1;
+        let tok = &self.tokens[self.pos - 1];
+        let mut node = Node::new_node_num(tok.val, span);
+        node.fval = tok.fval;
+        node.ty = tok.ty.clone().map(Box::new);
+        Ok(node)
+    }
+
+    // va_start(ap, last) | va_arg(ap, typename) | va_end(ap)
+    //
+    // These are built into the compiler, with ap a va_list. va_start makes
+    // ap point at the first unnamed argument, and each va_arg reads the next
+    // one as the given type.
+    fn va_builtin(&mut self, name: &str, span: Span) -> Result<Node, CompileError> {
+        let mut ap = self.assign()?;
+        ap.check_type();
+        if !ap.ty.as_ref().unwrap().is_va_list() {
+            return Err(CompileError::new(
+                ErrorKind::TypeMismatch,
+                ap.span,
+                format!("first argument to '{}' is not of type 'va_list'", name),
+            ));
+        }
+
+        let node = match name {
+            "va_start" => {
+                self.expect(P::Comma)?;
+                self.assign()?;
+                let va_area = match &self.va_area {
+                    Some(va_area) => va_area.clone(),
+                    None => {
+                        return Err(CompileError::new(
+                            ErrorKind::TypeMismatch,
+                            span,
+                            "'va_start' used in function with fixed arguments".to_string(),
+                        ))
+                    }
+                };
+                Node {
+                    lvar: Some(Box::new(va_area)),
+                    func_ty: Some(Box::new(self.func_ty.clone())),
+                    ty: Some(Box::new(Type::new_void())),
+                    ..Node::new_unary(NodeKind::NdVaStart, Box::new(ap), span)
+                }
+            }
+            "va_arg" => {
+                self.expect(P::Comma)?;
+                let ty_span = self.span();
+                let ty = self.typename()?;
+                if ty.kind == TypeKind::TyFloat {
+                    return Err(CompileError::new(
+                        ErrorKind::TypeMismatch,
+                        ty_span,
+                        "'float' is promoted to 'double' when passed through '...'".to_string(),
+                    ));
+                }
+                if !ty.is_numeric() && ty.kind != TypeKind::TyPtr {
+                    return Err(CompileError::new(
+                        ErrorKind::TypeMismatch,
+                        ty_span,
+                        "va_arg only supports arithmetic and pointer types".to_string(),
+                    ));
+                }
+                Node {
+                    ty: Some(Box::new(ty)),
+                    ..Node::new_unary(NodeKind::NdVaArg, Box::new(ap), span)
+                }
+            }
+            // there is nothing to release
+            _ => ap,
+        };
+        self.expect(P::RParen)?;
+
+        Ok(node)
+    }
+
+    // Reads a string literal and returns its contents. Adjacent string
+    // literals are concatenated.
+    fn string_literal(&mut self) -> Vec<u8> {
+        let mut contents = vec![];
+        while self.tokens[self.pos].kind == TokenKind::TkStr {
+            contents.pop();
+            contents.extend_from_slice(&self.tokens[self.pos].contents);
+            self.pos += 1;
+        }
+        contents
+    }
+
+    // postfix = primary ("[" expr "]" | "." ident | "->" ident | "++" | "--")*
+    fn postfix(&mut self) -> Result<Node, CompileError> {
+        let mut node = self.primary()?;
+
+        loop {
+            let span = self.span();
+            if self.consume(P::LBracket) {
+                let idx = self.expr()?;
+                self.expect(P::RBracket)?;
+                node = Node::new_deref(
+                    Box::new(Node::new_add(Box::new(node), Box::new(idx), span)?),
+                    span,
+                )?;
+                continue;
+            }
+
+            if self.consume(P::Dot) {
+                let name = self.expect_ident()?;
+                node = self.struct_ref(node, &name, span)?;
+                continue;
+            }
+
+            if self.consume(P::Arrow) {
+                // x->y is short for (*x).y
+                let name = self.expect_ident()?;
+                let deref = Node::new_deref(Box::new(node), span)?;
+                node = self.struct_ref(deref, &name, span)?;
+                continue;
+            }
+
+            if self.consume(P::Inc) {
+                node = self.post_inc_dec(node, P::Plus, span)?;
+                continue;
+            }
+            if self.consume(P::Dec) {
+                node = self.post_inc_dec(node, P::Minus, span)?;
+                continue;
+            }
+
+            break;
+        }
+
+        Ok(node)
+    }
+
+    // unary = ("+" | "-" | "&" | "*" | "~" | "!" | "++" | "--") unary
+    //       | "(" typename ")" unary
+    //       | ("sizeof" | "_Alignof") unary
+    //       | ("sizeof" | "_Alignof") "(" typename ")"
+    //       | postfix
+    fn unary(&mut self) -> Result<Node, CompileError> {
+        let span = self.span();
+        if self.tokens[self.pos].is(P::Plus) || self.tokens[self.pos].is(P::Minus) {
+            let is_minus = self.tokens[self.pos].is(P::Minus);
+            self.pos += 1;
+            let mut operand = self.unary()?;
+            operand.check_type();
+            if !operand.ty.as_ref().unwrap().is_numeric() {
+                let name = if is_minus { "minus" } else { "plus" };
+                return Err(CompileError::new(
+                    ErrorKind::TypeMismatch,
+                    span,
+                    format!("wrong type argument to unary {}", name),
+                ));
+            }
+            if !is_minus {
+                return Ok(operand);
+            }
+            let zero = Node::new_node_num(0, span);
+            return Node::new_sub(Box::new(zero), Box::new(operand), span);
+        }
+        if self.consume(P::Amp) {
+            return Ok(Node::new_unary(
+                NodeKind::NdAddr,
+                Box::new(self.unary()?),
+                span,
+            ));
+        }
+        if self.consume(P::Star) {
+            return Node::new_deref(Box::new(self.unary()?), span);
+        }
+        if self.consume(P::Tilde) {
+            let mut operand = self.unary()?;
+            operand.check_type();
+            if !operand.ty.as_ref().unwrap().is_integer() {
+                return Err(CompileError::new(
+                    ErrorKind::TypeMismatch,
+                    span,
+                    "wrong type argument to bit-complement".to_string(),
+                ));
+            }
+            return Ok(Node::new_unary(NodeKind::NdBitNot, Box::new(operand), span));
+        }
+        if self.consume(P::Not) {
+            let mut operand = self.unary()?;
+            operand.check_scalar()?;
+            return Ok(Node::new_unary(NodeKind::NdNot, Box::new(operand), span));
+        }
+        // ++x is x += 1, and --x is x -= 1
+        if self.consume(P::Inc) {
+            let operand = self.unary()?;
+            return self.compound_assign(P::Plus, operand, Node::new_node_num(1, span), span);
+        }
+        if self.consume(P::Dec) {
+            let operand = self.unary()?;
+            return self.compound_assign(P::Minus, operand, Node::new_node_num(1, span), span);
+        }
+        if self.tokens[self.pos].is(P::LParen) && self.is_typename_at(self.pos + 1) {
+            self.expect(P::LParen)?;
+            let ty = self.typename()?;
+            self.expect(P::RParen)?;
+            let mut operand = self.unary()?;
+            operand.check_type();
+            let from = operand.ty.as_ref().unwrap();
+            if ty.kind != TypeKind::TyVoid && (ty.is_struct() || from.is_struct()) && ty != **from {
+                return Err(CompileError::new(
+                    ErrorKind::TypeMismatch,
+                    span,
+                    "conversion to or from a struct type requested".to_string(),
+                ));
+            }
+            return Ok(Node {
+                span,
+                ..Node::new_cast(Box::new(operand), ty)
+            });
+        }
+        if self.tokens[self.pos].is_kw(Kw::Sizeof) || self.tokens[self.pos].is_kw(Kw::Alignof) {
+            let is_sizeof = self.consume_kw(Kw::Sizeof);
+            if !is_sizeof {
+                self.pos += 1;
+            }
+
+            let ty = if self.tokens[self.pos].is(P::LParen) && self.is_typename_at(self.pos + 1) {
+                self.expect(P::LParen)?;
+                let ty = self.typename()?;
+                self.expect(P::RParen)?;
+                ty
+            } else {
+                let mut node = self.unary()?;
+                node.check_type();
+                *node.ty.unwrap()
+            };
+            let ty = self.complete_struct(&ty);
+
+            let val = if is_sizeof { ty.size } else { ty.align() };
+            return Ok(Node {
+                ty: Some(Box::new(Type::new_ulong())),
+                ..Node::new_node_num(val as u64, span)
+            });
+        }
+
+        self.postfix()
+    }
+
+    // Builds the node for the binary operator op, for the compound
+    // assignments as well as for the operators themselves.
+    fn binary(&mut self, op: P, lhs: Node, rhs: Node, span: Span) -> Result<Node, CompileError> {
+        let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
+        let kind = match op {
+            P::Plus => return Node::new_add(lhs, rhs, span),
+            P::Minus => return Node::new_sub(lhs, rhs, span),
+            P::Star => NodeKind::NdMul,
+            P::Slash => NodeKind::NdDiv,
+            P::Percent => NodeKind::NdMod,
+            P::Amp => NodeKind::NdBitAnd,
+            P::Pipe => NodeKind::NdBitOr,
+            P::Caret => NodeKind::NdBitXor,
+            P::Shl => NodeKind::NdShl,
+            _ => NodeKind::NdShr,
+        };
+
+        let mut node = Node::new_binary(kind, lhs, rhs, span);
+        node.lhs.as_mut().unwrap().check_type();
+        node.rhs.as_mut().unwrap().check_type();
+        let is_integer =
+            |node: &Option<Box<Node>>| node.as_ref().unwrap().ty.as_ref().unwrap().is_integer();
+        let is_numeric =
+            |node: &Option<Box<Node>>| node.as_ref().unwrap().ty.as_ref().unwrap().is_numeric();
+        let valid = match op {
+            P::Star | P::Slash => is_numeric(&node.lhs) && is_numeric(&node.rhs),
+            _ => is_integer(&node.lhs) && is_integer(&node.rhs),
+        };
+        if !valid {
+            return Err(CompileError::new(
+                ErrorKind::TypeMismatch,
+                span,
+                format!("invalid operands to binary {}", op.as_str()),
+            ));
+        }
+        Ok(node)
+    }
+
+    // Adds a local with no name, for a value the compiler needs to keep.
+    fn new_temp_lvar(&mut self, ty: Type) -> LVar {
+        let lvar = LVar::new_lvar(self.temp_locals.len(), ty, String::new());
+        self.temp_locals.push(lvar.clone());
+        lvar
+    }
+
+    // Converts lhs op= rhs to (tmp = &lhs, *tmp = *tmp op rhs), so that lhs
+    // is evaluated only once.
+    fn compound_assign(
+        &mut self,
+        op: P,
+        lhs: Node,
+        rhs: Node,
+        span: Span,
+    ) -> Result<Node, CompileError> {
+        let (store_addr, tmp) = self.temp_address(lhs, span)?;
+        let value = self.binary(op, deref_lvar(&tmp, span), rhs, span)?;
+        let assign = Node::new_binary(
+            NodeKind::NdAs,
+            Box::new(deref_lvar(&tmp, span)),
+            Box::new(value),
+            span,
+        );
+
+        Ok(Node::new_binary(
+            NodeKind::NdComma,
+            Box::new(store_addr),
+            Box::new(assign),
+            span,
+        ))
+    }
+
+    // Builds tmp = &lhs for a new temporary tmp, checking that lhs is an
+    // lvalue, and returns it with tmp.
+    fn temp_address(&mut self, mut lhs: Node, span: Span) -> Result<(Node, LVar), CompileError> {
+        lhs.check_type();
+        match lhs.kind {
+            NodeKind::NdLv | NodeKind::NdGv | NodeKind::NdDeref | NodeKind::NdMember => {}
+            _ => {
+                return Err(CompileError::new(
+                    ErrorKind::NotLvalue,
+                    lhs.span,
+                    "lvalue required as left operand of assignment".to_string(),
+                ))
+            }
+        }
+        let tmp = self.new_temp_lvar(lhs.ty.clone().unwrap().pointer_to());
+        let addr = Node::new_unary(NodeKind::NdAddr, Box::new(lhs), span);
+        let store_addr = Node::new_binary(
+            NodeKind::NdAs,
+            Box::new(Node::new_node_lv(Box::new(tmp.clone()), span)),
+            Box::new(addr),
+            span,
+        );
+        Ok((store_addr, tmp))
+    }
+
+    // Converts x++ to (tmp = &x, old = *tmp, *tmp = *tmp + 1, old), and
+    // likewise for x--. The old value is kept rather than recomputed, which
+    // would be wrong for a _Bool and lose precision for a float.
+    fn post_inc_dec(&mut self, operand: Node, op: P, span: Span) -> Result<Node, CompileError> {
+        let (store_addr, tmp) = self.temp_address(operand, span)?;
+        let ty = *tmp.ty.ptr_to.clone().unwrap();
+        let old = self.new_temp_lvar(ty);
+        let old_lv = || Node::new_node_lv(Box::new(old.clone()), span);
+        let save = Node::new_binary(
+            NodeKind::NdAs,
+            Box::new(old_lv()),
+            Box::new(deref_lvar(&tmp, span)),
+            span,
+        );
+        let value = self.binary(
+            op,
+            deref_lvar(&tmp, span),
+            Node::new_node_num(1, span),
+            span,
+        )?;
+        let assign = Node::new_binary(
+            NodeKind::NdAs,
+            Box::new(deref_lvar(&tmp, span)),
+            Box::new(value),
+            span,
+        );
+
+        let comma =
+            |lhs, rhs| Node::new_binary(NodeKind::NdComma, Box::new(lhs), Box::new(rhs), span);
+        Ok(comma(store_addr, comma(save, comma(assign, old_lv()))))
+    }
+
+    // mul = unary ("*" unary | "/" unary | "%" unary)*
+    fn mul(&mut self) -> Result<Node, CompileError> {
+        let mut lhs = self.unary()?;
+
+        loop {
+            let span = self.span();
+            let op = match self.tokens[self.pos].kind {
+                TokenKind::TkPunct(op @ (P::Star | P::Slash | P::Percent)) => op,
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.unary()?;
+            lhs = self.binary(op, lhs, rhs, span)?;
+        }
+
+        Ok(lhs)
+    }
+
+    // add = mul ( "+" mul | "-" mul )*
+    fn add(&mut self) -> Result<Node, CompileError> {
+        let mut lhs = self.mul()?;
+
+        loop {
+            let span = self.span();
+            if self.consume(P::Plus) {
+                lhs = Node::new_add(Box::new(lhs), Box::new(self.mul()?), span)?;
+            } else if self.consume(P::Minus) {
+                lhs = Node::new_sub(Box::new(lhs), Box::new(self.mul()?), span)?;
+            } else {
+                break;
+            }
+        }
+
+        Ok(lhs)
+    }
+
+    // shift = add ("<<" add | ">>" add)*
+    fn shift(&mut self) -> Result<Node, CompileError> {
+        let mut lhs = self.add()?;
+
+        loop {
+            let span = self.span();
+            let op = match self.tokens[self.pos].kind {
+                TokenKind::TkPunct(op @ (P::Shl | P::Shr)) => op,
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.add()?;
+            lhs = self.binary(op, lhs, rhs, span)?;
+        }
+
+        Ok(lhs)
+    }
+
+    // relational = shift ( ">" shift | "<" shift | ">=" shift | "<=" shift )*
+    fn relational(&mut self) -> Result<Node, CompileError> {
+        let mut lhs = self.shift()?;
+
+        loop {
+            let span = self.span();
+            let op = match self.tokens[self.pos].kind {
+                TokenKind::TkPunct(op @ (P::Gt | P::Lt | P::Ge | P::Le)) => op,
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.shift()?;
+            lhs = Node::new_comparison(op, Box::new(lhs), Box::new(rhs), span)?;
+        }
+
+        Ok(lhs)
+    }
+
+    // equality = relational ( "==" relational | "!=" relational )*
+    fn equality(&mut self) -> Result<Node, CompileError> {
+        let mut lhs = self.relational()?;
+
+        loop {
+            let span = self.span();
+            let op = match self.tokens[self.pos].kind {
+                TokenKind::TkPunct(op @ (P::EqEq | P::Ne)) => op,
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.relational()?;
+            lhs = Node::new_comparison(op, Box::new(lhs), Box::new(rhs), span)?;
+        }
+
+        Ok(lhs)
+    }
+
+    // bitand = equality ("&" equality)*
+    // bitxor = bitand ("^" bitand)*
+    // bitor = bitxor ("|" bitxor)*
+    //
+    // Parses the bitwise operator at level 0, 1 or 2 of the above.
+    fn bitwise(&mut self, level: usize) -> Result<Node, CompileError> {
+        let op = [P::Amp, P::Caret, P::Pipe][level];
+        let operand = |parser: &mut Self| {
+            if level == 0 {
+                parser.equality()
+            } else {
+                parser.bitwise(level - 1)
+            }
+        };
+
+        let mut lhs = operand(self)?;
+        loop {
+            let span = self.span();
+            if !self.consume(op) {
+                break;
+            }
+            let rhs = operand(self)?;
+            lhs = self.binary(op, lhs, rhs, span)?;
+        }
+
+        Ok(lhs)
+    }
+
+    // logand = bitor ("&&" bitor)*
+    fn logand(&mut self) -> Result<Node, CompileError> {
+        let mut lhs = self.bitwise(2)?;
+
+        loop {
+            let span = self.span();
+            if self.consume(P::AndAnd) {
+                let mut rhs = self.bitwise(2)?;
+                lhs.check_scalar()?;
+                rhs.check_scalar()?;
+                lhs = Node::new_binary(NodeKind::NdLogAnd, Box::new(lhs), Box::new(rhs), span);
+            } else {
+                break;
+            }
+        }
+
+        Ok(lhs)
+    }
+
+    // logor = logand ("||" logand)*
+    fn logor(&mut self) -> Result<Node, CompileError> {
+        let mut lhs = self.logand()?;
+
+        loop {
+            let span = self.span();
+            if self.consume(P::OrOr) {
+                let mut rhs = self.logand()?;
+                lhs.check_scalar()?;
+                rhs.check_scalar()?;
+                lhs = Node::new_binary(NodeKind::NdLogOr, Box::new(lhs), Box::new(rhs), span);
+            } else {
+                break;
+            }
+        }
+
+        Ok(lhs)
+    }
+
+    // conditional = logor ("?" expr ":" conditional)?
+    fn conditional(&mut self) -> Result<Node, CompileError> {
+        let cond = self.logor()?;
+
+        let span = self.span();
+        if !self.consume(P::Question) {
+            return Ok(cond);
+        }
+        let mut node = Node::new_node(NodeKind::NdCond, span);
+        let mut cond = cond;
+        cond.check_scalar()?;
+        node.cond = Some(Box::new(cond));
+        node.then = Some(Box::new(self.expr()?));
+        self.expect(P::Colon)?;
+        node.els = Some(Box::new(self.conditional()?));
+
+        Ok(node)
+    }
+
+    // assign = conditional (("=" | "+=" | "-=" | "*=" | "/=" | "%=" | "&=" | "|=" | "^="
+    //                        | "<<=" | ">>=") assign)?
+    fn assign(&mut self) -> Result<Node, CompileError> {
+        let mut lhs = self.conditional()?;
+
+        let span = self.span();
+        if self.consume(P::Assign) {
+            let mut rhs = self.assign()?;
+            lhs.check_type();
+            rhs.check_type();
+            let (lhs_ty, rhs_ty) = (lhs.ty.as_ref().unwrap(), rhs.ty.as_ref().unwrap());
+            if lhs_ty.kind == TypeKind::TyArr {
+                return Err(CompileError::new(
+                    ErrorKind::NotLvalue,
+                    span,
+                    "assignment to expression with array type".to_string(),
+                ));
+            }
+            self.check_assignable(lhs_ty, rhs_ty, "assigning", span)?;
+            lhs = Node::new_binary(NodeKind::NdAs, Box::new(lhs), Box::new(rhs), span);
+        } else if let Some(&(_, op)) = COMPOUND_ASSIGNS
+            .iter()
+            .find(|(assign, _)| self.tokens[self.pos].is(*assign))
+        {
+            self.pos += 1;
+            let rhs = self.assign()?;
+            lhs = self.compound_assign(op, lhs, rhs, span)?;
+        }
+
+        Ok(lhs)
+    }
+
+    // expr = assign ("," assign)*
+    fn expr(&mut self) -> Result<Node, CompileError> {
+        let mut lhs = self.assign()?;
+
+        loop {
+            let span = self.span();
+            if self.consume(P::Comma) {
+                lhs = Node::new_binary(
+                    NodeKind::NdComma,
+                    Box::new(lhs),
+                    Box::new(self.assign()?),
+                    span,
+                );
+            } else {
+                break;
+            }
+        }
+
+        Ok(lhs)
+    }
+
+    // declaration = basetype declarator ("=" initializer)?
+    //               ("," declarator ("=" initializer)?)* ";"
+    //             | basetype typedef
+    fn declaration(&mut self) -> Result<Node, CompileError> {
+        let mut node = Node::new_node(NodeKind::NdBlock, self.span());
+        let mut attr = VarAttr::default();
+        let base = self.basetype(Some(&mut attr))?;
+        if attr.is_typedef {
+            self.parse_typedef(base)?;
+            return Ok(node);
+        }
+        // a declaration of only a struct tag
+        if self.consume(P::Semi) {
+            return Ok(node);
+        }
+
+        loop {
+            let (ty, name, span) = self.declarator(base.clone())?;
+            // a function prototype in a block, whose parameters are not locals
+            if self.consume(P::LParen) {
+                check_return_type(&ty, span)?;
+                let locals = self.temp_locals.len();
+                self.enter_scope();
+                let params = self.params();
+                self.leave_scope();
+                self.temp_locals.truncate(locals);
+
+                let (params, is_variadic) = params?;
+                let ty = Type::func_type(ty, params, is_variadic);
+                self.declare_function(&name, &ty, span)?;
+                if !self.consume(P::Comma) {
+                    break;
+                }
+                continue;
+            }
+            if attr.is_static {
+                self.static_lvar(ty, name, span)?;
+                if !self.consume(P::Comma) {
+                    break;
+                }
+                continue;
+            }
+            let mut lvar = self.new_lvar(ty, name, span)?;
+
+            if self.consume(P::Assign) {
+                let init = self.initializer(&lvar.ty)?;
+                if lvar.ty.incomplete {
+                    // the length of the array is now known
+                    lvar.ty = init.ty.clone();
+                    self.temp_locals[lvar.id] = lvar.clone();
+                    let scope = self.scopes.last_mut().unwrap();
+                    scope
+                        .vars
+                        .insert(lvar.name.clone(), VarScope::Local(lvar.clone()));
+                }
+
+                let node_lv = Node::new_node_lv(Box::new(lvar.clone()), span);
+                lvar_initializer(&init, node_lv, &mut node.blocks)?;
+            }
+            if lvar.ty.incomplete {
+                let msg = if lvar.ty.is_struct() {
+                    format!("storage size of '{}' isn't known", lvar.name)
+                } else {
+                    format!("array size missing in '{}'", lvar.name)
+                };
+                return Err(CompileError::new(ErrorKind::UnexpectedToken, span, msg));
+            }
+
+            if !self.consume(P::Comma) {
+                break;
+            }
+        }
+        self.expect(P::Semi)?;
+
+        Ok(node)
+    }
+
+    // A static local variable lives as long as the program, so it is stored
+    // like a global, under a name made unique by a suffix, and initialized
+    // only once, with a constant.
+    fn static_lvar(&mut self, ty: Type, name: String, span: Span) -> Result<(), CompileError> {
+        if self.scopes.last().unwrap().vars.contains_key(&name) {
+            return Err(CompileError::new(
+                ErrorKind::Redeclaration,
+                span,
+                format!("redeclaration of '{}'", name),
+            ));
+        }
+        let mut global = Global {
+            gvar: GVar {
+                name: format!("{}.{}", name, self.globals.len()),
+                ty,
+            },
+            data: None,
+            relocs: vec![],
+            is_static: true,
+        };
+        if self.consume(P::Assign) {
+            self.gvar_initializer(&mut global)?;
+        }
+        if global.gvar.ty.incomplete {
+            return Err(CompileError::new(
+                ErrorKind::UnexpectedToken,
+                span,
+                format!("storage size of '{}' isn't known", name),
+            ));
+        }
+
+        let scope = self.scopes.last_mut().unwrap();
+        scope
+            .vars
+            .insert(name, VarScope::Global(global.gvar.clone()));
+        self.globals.push(global);
+        Ok(())
+    }
+
+    // initializer = string | "{" (initializer ("," initializer)*)? ","? "}" | assign
+    //
+    // As in C11, the braces around the initializer of a subarray may be
+    // left out, and a string literal may initialize an array of char.
+    fn initializer(&mut self, ty: &Type) -> Result<Initializer, CompileError> {
+        let mut init = Initializer::new(ty);
+        self.initializer2(&mut init)?;
+        init.ty = init.complete_type();
+        Ok(init)
+    }
+
+    fn initializer2(&mut self, init: &mut Initializer) -> Result<(), CompileError> {
+        if init.ty.kind == TypeKind::TyArr {
+            let elem = init.ty.ptr_to.as_ref().unwrap();
+            if elem.kind == TypeKind::TyChar && self.tokens[self.pos].kind == TokenKind::TkStr {
+                return self.string_initializer(init);
+            }
+            if self.tokens[self.pos].is(P::LBrace) {
+                return self.array_initializer1(init);
+            }
+            return self.array_initializer2(init);
+        }
+
+        if init.ty.is_struct() {
+            if self.tokens[self.pos].is(P::LBrace) {
+                return self.struct_initializer1(init);
+            }
+
+            // a struct may be initialized with another struct, or with the
+            // initializers of its members without braces
+            let saved = (self.pos, self.strings.len(), self.diagnostics.len());
+            let span = self.span();
+            let mut expr = self.assign()?;
+            expr.check_type();
+            if expr.ty.as_ref().unwrap().is_struct() {
+                self.check_assignable(&init.ty, expr.ty.as_ref().unwrap(), "initializing", span)?;
+                init.expr = Some(Box::new(expr));
+                return Ok(());
+            }
+            self.pos = saved.0;
+            self.strings.truncate(saved.1);
+            self.diagnostics.truncate(saved.2);
+            return self.struct_initializer2(init);
+        }
+
+        // a scalar may be enclosed in braces
+        let braced = self.consume(P::LBrace);
+        let span = self.span();
+        let mut expr = self.assign()?;
+        expr.check_type();
+        self.check_assignable(&init.ty, expr.ty.as_ref().unwrap(), "initializing", span)?;
+        init.expr = Some(Box::new(expr));
+        if braced {
+            self.consume(P::Comma);
+            return self.expect(P::RBrace);
+        }
+        Ok(())
+    }
+
+    fn string_initializer(&mut self, init: &mut Initializer) -> Result<(), CompileError> {
+        let span = self.span();
+        let contents = self.string_literal();
+
+        for (i, b) in contents.iter().enumerate() {
+            match init.child(i) {
+                Some(child) => child.expr = Some(Box::new(Node::new_node_num(*b as u64, span))),
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    // "{" initializer ("," initializer)* ","? "}"
+    fn array_initializer1(&mut self, init: &mut Initializer) -> Result<(), CompileError> {
+        self.expect(P::LBrace)?;
+
+        let mut i = 0;
+        while !self.consume_end() {
+            if i > 0 {
+                self.expect(P::Comma)?;
+            }
+            match init.child(i) {
+                Some(child) => self.initializer2(child)?,
+                None => self.skip_excess_element()?,
+            }
+            i += 1;
+        }
+
+        Ok(())
+    }
+
+    // initializer ("," initializer)*, with the braces of a subarray left out
+    fn array_initializer2(&mut self, init: &mut Initializer) -> Result<(), CompileError> {
+        let mut i = 0;
+        while i < init.children.len() && !self.is_end() {
+            if i > 0 {
+                self.expect(P::Comma)?;
+            }
+            self.initializer2(&mut init.children[i])?;
+            i += 1;
+        }
+
+        Ok(())
+    }
+
+    // "{" initializer ("," initializer)* ","? "}"
+    fn struct_initializer1(&mut self, init: &mut Initializer) -> Result<(), CompileError> {
+        self.expect(P::LBrace)?;
+
+        let mut i = 0;
+        while !self.consume_end() {
+            if i > 0 {
+                self.expect(P::Comma)?;
+            }
+            if i < init.initialized_members() {
+                self.initializer2(&mut init.children[i])?;
+            } else {
+                self.skip_excess_element()?;
+            }
+            i += 1;
+        }
+
+        Ok(())
+    }
+
+    // initializer ("," initializer)*, with the braces of a struct left out
+    fn struct_initializer2(&mut self, init: &mut Initializer) -> Result<(), CompileError> {
+        let mut i = 0;
+        while i < init.initialized_members() && !self.is_end() {
+            if i > 0 {
+                self.expect(P::Comma)?;
+            }
+            self.initializer2(&mut init.children[i])?;
+            i += 1;
+        }
+
+        Ok(())
+    }
+
+    // Parses and discards an initializer for an element past the end of an
+    // array. C11 only requires a warning for it.
+    fn skip_excess_element(&mut self) -> Result<(), CompileError> {
+        if self.consume(P::LBrace) {
+            self.skip_excess_element()?;
+            while !self.consume_end() {
+                self.expect(P::Comma)?;
+                self.skip_excess_element()?;
+            }
+            return Ok(());
+        }
+        self.assign()?;
+        Ok(())
+    }
+
+    // the end of a brace-enclosed initializer list, with an optional trailing comma
+    fn is_end(&self) -> bool {
+        self.tokens[self.pos].is(P::RBrace)
+            || (self.tokens[self.pos].is(P::Comma) && self.tokens[self.pos + 1].is(P::RBrace))
+    }
+
+    fn consume_end(&mut self) -> bool {
+        if self.is_end() {
+            self.consume(P::Comma);
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    // compound_stmt = stmt* "}"
+    fn compound_stmt(&mut self) -> Result<Vec<Node>, CompileError> {
+        let mut blocks = vec![];
+
+        while !self.consume(P::RBrace) {
+            if self.tokens[self.pos].kind == TokenKind::TkEof {
+                return Err(CompileError::new(
+                    ErrorKind::UnexpectedToken,
+                    self.span(),
+                    "expected } but got end of input".to_string(),
+                ));
+            }
+
+            match self.stmt() {
+                Ok(mut node) => {
+                    node.check_type();
+                    blocks.push(node);
+                }
+                Err(err) => {
+                    self.recover(err)?;
+                    self.skip_stmt();
+                }
+            }
+        }
+
+        Ok(blocks)
+    }
+
+    // stmt = "return" expr? ";"
+    //        | "{" stmt* "}"
+    //        | "if" "(" cond ")" stmt ( "else" stmt )?
+    //        | "while" "(" cond ")" stmt
+    //        | "do" stmt "while" "(" cond ")" ";"
+    //        | "for" "(" (declaration | expr? ";") expr? ";" expr? ")" stmt
+    //        | "switch" "(" expr ")" stmt
+    //        | "case" const_expr ":" stmt
+    //        | "default" ":" stmt
+    //        | "break" ";"
+    //        | "continue" ";"
+    //        | "goto" ident ";"
+    //        | ident ":" stmt
+    //        | declaration
+    //        | expr? ";"
+    fn stmt(&mut self) -> Result<Node, CompileError> {
+        let mut node;
+
+        let span = self.span();
+        if self.consume(P::Semi) {
+            return Ok(Node::new_node(NodeKind::NdBlock, span));
+        }
+
+        if self.tokens[self.pos].kind == TokenKind::TkIdent
+            && self.tokens[self.pos + 1].is(P::Colon)
+        {
+            return self.labeled_stmt();
+        }
+
+        if self.consume(P::LBrace) {
+            node = Node::new_node(NodeKind::NdBlock, span);
+            self.enter_scope();
+            let blocks = self.compound_stmt();
+            self.leave_scope();
+            node.blocks = blocks?;
+            return Ok(node);
+        }
+
+        if self.consume_kw(Kw::Return) {
+            node = Node::new_node(NodeKind::NdRt, span);
+            if self.consume(P::Semi) {
+                return Ok(node);
+            }
+            let expr_span = self.span();
+            let mut expr = self.expr()?;
+            expr.check_type();
+            // the value is converted to the return type, as if by assignment
+            let return_ty = *self.func_ty.return_ty.clone().unwrap();
+            self.check_assignable(
+                &return_ty,
+                expr.ty.as_ref().unwrap(),
+                "returning",
+                expr_span,
+            )?;
+            if return_ty.is_numeric() {
+                expr = Node::new_cast(Box::new(expr), return_ty);
+            }
+            node.lhs = Some(Box::new(expr));
+        } else if self.consume_kw(Kw::If) {
+            node = Node::new_node(NodeKind::NdIf, span);
+            self.expect(P::LParen)?;
+            let mut cond = self.expr()?;
+            cond.check_scalar()?;
+            node.cond = Some(Box::new(cond));
+            self.expect(P::RParen)?;
+            let mut then = self.stmt()?;
+            then.check_type();
+            node.then = Some(Box::new(then));
+            if self.consume_kw(Kw::Else) {
+                let mut els = self.stmt()?;
+                els.check_type();
+                node.els = Some(Box::new(els));
+            }
 
+            return Ok(node);
+        } else if self.consume_kw(Kw::While) {
+            node = Node::new_node(NodeKind::NdWhile, span);
+            self.expect(P::LParen)?;
+            let mut cond = self.expr()?;
+            cond.check_scalar()?;
+            node.cond = Some(Box::new(cond));
+            self.expect(P::RParen)?;
+            node.then = Some(Box::new(self.loop_body()?));
+
+            return Ok(node);
+        } else if self.consume_kw(Kw::Do) {
+            node = Node::new_node(NodeKind::NdDo, span);
+            node.then = Some(Box::new(self.loop_body()?));
+            self.expect_kw(Kw::While)?;
+            self.expect(P::LParen)?;
+            let mut cond = self.expr()?;
+            cond.check_scalar()?;
+            node.cond = Some(Box::new(cond));
+            self.expect(P::RParen)?;
+        } else if self.consume_kw(Kw::For) {
+            // a declaration in the init clause is scoped to the loop
+            self.enter_scope();
+            let node = self.for_stmt(span);
+            self.leave_scope();
+            return node;
+        } else if self.consume_kw(Kw::Switch) {
+            return self.switch_stmt(span);
+        } else if self.consume_kw(Kw::Case) {
+            return self.case_stmt(span);
+        } else if self.consume_kw(Kw::Default) {
+            return self.default_stmt(span);
+        } else if self.consume_kw(Kw::Break) {
+            if self.loop_depth == 0 && self.switches.is_empty() {
+                return Err(CompileError::new(
+                    ErrorKind::UnexpectedToken,
+                    span,
+                    "break statement not within loop or switch".to_string(),
+                ));
+            }
+            node = Node::new_node(NodeKind::NdBreak, span);
+        } else if self.consume_kw(Kw::Continue) {
+            if self.loop_depth == 0 {
+                return Err(CompileError::new(
+                    ErrorKind::UnexpectedToken,
+                    span,
+                    "continue statement not within a loop".to_string(),
+                ));
+            }
+            node = Node::new_node(NodeKind::NdContinue, span);
+        } else if self.consume_kw(Kw::Goto) {
+            node = Node::new_node(NodeKind::NdGoto, span);
+            let label_span = self.span();
+            node.label = self.expect_ident()?;
+            self.gotos.push((node.label.clone(), label_span));
+        } else if self.is_typename() {
+            return self.declaration();
+        } else {
+            node = Node::new_unary(NodeKind::NdExprStmt, Box::new(self.expr()?), span);
+        }
+
+        self.expect(P::Semi)?;
+
+        Ok(node)
+    }
+
+    // for_stmt = "(" (declaration | expr? ";") expr? ";" expr? ")" stmt
+    fn for_stmt(&mut self, span: Span) -> Result<Node, CompileError> {
+        let mut node = Node::new_node(NodeKind::NdFor, span);
+        self.expect(P::LParen)?;
+        if self.is_typename() {
+            let mut preop = self.declaration()?;
+            preop.check_type();
+            node.preop = Some(Box::new(preop));
+        } else {
+            if !self.tokens[self.pos].is(P::Semi) {
+                let span = self.span();
+                let mut preop = Node::new_unary(NodeKind::NdExprStmt, Box::new(self.expr()?), span);
+                preop.check_type();
+                node.preop = Some(Box::new(preop));
+            }
+            self.expect(P::Semi)?;
+        }
+        if !self.tokens[self.pos].is(P::Semi) {
+            let mut cond = self.expr()?;
+            cond.check_scalar()?;
+            node.cond = Some(Box::new(cond));
+        }
+        self.expect(P::Semi)?;
+        if !self.tokens[self.pos].is(P::RParen) {
+            let mut postop = self.expr()?;
+            postop.check_type();
+            node.postop = Some(Box::new(postop));
+        }
+        self.expect(P::RParen)?;
+        node.then = Some(Box::new(self.loop_body()?));
+
+        Ok(node)
+    }
+
+    // The body of a loop, in which break and continue refer to the loop.
+    fn loop_body(&mut self) -> Result<Node, CompileError> {
+        self.loop_depth += 1;
+        let body = self.stmt();
+        self.loop_depth -= 1;
+
+        let mut body = body?;
+        body.check_type();
+        Ok(body)
+    }
+
+    // switch_stmt = "(" expr ")" stmt
+    //
+    // The case labels in the body are collected into the switch node; a case
+    // node only records its index among them.
+    fn switch_stmt(&mut self, span: Span) -> Result<Node, CompileError> {
+        let mut node = Node::new_node(NodeKind::NdSwitch, span);
+        self.expect(P::LParen)?;
+        let mut cond = self.expr()?;
+        cond.check_type();
+        let ty = cond.ty.as_ref().unwrap();
+        if !ty.is_integer() {
+            return Err(CompileError::new(
+                ErrorKind::TypeMismatch,
+                cond.span,
+                "switch quantity not an integer".to_string(),
+            ));
+        }
+        let ty = Type::common_type(ty, ty);
+        node.cond = Some(Box::new(Node::new_cast(Box::new(cond), ty.clone())));
+        self.expect(P::RParen)?;
+
+        self.switches.push(Switch {
+            ty,
+            cases: vec![],
+            has_default: false,
+        });
+        let body = self.stmt();
+        let switch = self.switches.pop().unwrap();
+
+        let mut body = body?;
+        body.check_type();
+        node.then = Some(Box::new(body));
+        node.cases = switch.cases;
+        node.has_default = switch.has_default;
+        Ok(node)
+    }
+
+    // case_stmt = const_expr ":" stmt
+    fn case_stmt(&mut self, span: Span) -> Result<Node, CompileError> {
+        let val_span = self.span();
+        let val = self.const_expr().map_err(|err| match err.kind {
+            ErrorKind::NotConstant => CompileError::new(
+                ErrorKind::NotConstant,
+                err.span,
+                "case label does not reduce to an integer constant".to_string(),
+            ),
+            _ => err,
+        })?;
+        self.expect(P::Colon)?;
+
+        let switch = match self.switches.last_mut() {
+            Some(switch) => switch,
+            None => {
+                return Err(CompileError::new(
+                    ErrorKind::UnexpectedToken,
+                    span,
+                    "case label not within a switch statement".to_string(),
+                ))
+            }
+        };
+        // the value is converted to the type of the controlling expression
+        let val = match (switch.ty.size, switch.ty.is_unsigned) {
+            (4, true) => val as u32 as i64,
+            (4, false) => val as i32 as i64,
+            _ => val,
+        };
+        if switch.cases.contains(&val) {
+            return Err(CompileError::new(
+                ErrorKind::Redeclaration,
+                val_span,
+                "duplicate case value".to_string(),
+            ));
+        }
+        switch.cases.push(val);
+        let index = switch.cases.len() - 1;
+
+        let mut node = Node::new_unary(NodeKind::NdCase, Box::new(self.stmt()?), span);
+        node.val = index as u64;
+        Ok(node)
+    }
+
+    // default_stmt = ":" stmt
+    fn default_stmt(&mut self, span: Span) -> Result<Node, CompileError> {
+        self.expect(P::Colon)?;
+
+        let switch = match self.switches.last_mut() {
+            Some(switch) => switch,
+            None => {
+                return Err(CompileError::new(
+                    ErrorKind::UnexpectedToken,
+                    span,
+                    "'default' label not within a switch statement".to_string(),
+                ))
+            }
+        };
+        if switch.has_default {
+            return Err(CompileError::new(
+                ErrorKind::Redeclaration,
+                span,
+                "multiple default labels in one switch".to_string(),
+            ));
+        }
+        switch.has_default = true;
+
+        Ok(Node::new_unary(
+            NodeKind::NdDefault,
+            Box::new(self.stmt()?),
+            span,
+        ))
+    }
+
+    // labeled_stmt = ident ":" stmt
+    //
+    // Labels are in a namespace of their own that spans the whole function,
+    // so a goto may jump to a label further down.
+    fn labeled_stmt(&mut self) -> Result<Node, CompileError> {
+        let span = self.span();
+        let name = self.expect_ident()?;
+        self.expect(P::Colon)?;
+        if self.labels.contains(&name) {
+            return Err(CompileError::new(
+                ErrorKind::Redeclaration,
+                span,
+                format!("duplicate label '{}'", name),
+            ));
+        }
+        self.labels.push(name.clone());
+
+        let mut node = Node::new_unary(NodeKind::NdLabel, Box::new(self.stmt()?), span);
+        node.label = name;
+        Ok(node)
+    }
+
+    // param = basetype "*"* ident? type_suffix
+    //
+    // A parameter declared as an array is a pointer.
+    fn param(&mut self) -> Result<Type, CompileError> {
+        let mut ty = self.basetype(None)?;
+        ty = self.complete_struct(&ty);
+        ty = self.pointers(ty);
+        let span = self.span();
+        let name = match self.tokens[self.pos].kind {
+            TokenKind::TkIdent => Some(self.expect_ident()?),
+            _ => None,
+        };
+        ty = self.type_suffix(ty)?;
+        if ty.kind == TypeKind::TyArr {
+            ty = ty.ptr_to.unwrap().pointer_to();
+        }
+        if ty.is_struct() {
+            return Err(CompileError::new(
+                ErrorKind::TypeMismatch,
+                span,
+                "passing a struct by value is not supported; pass a pointer to it".to_string(),
+            ));
+        }
+
+        // an unnamed parameter still takes a slot, but cannot be referred to
+        match name {
+            Some(name) => {
+                self.new_lvar(ty.clone(), name, span)?;
+            }
+            None => {
+                let lvar = LVar::new_lvar(self.temp_locals.len(), ty.clone(), String::new());
+                self.temp_locals.push(lvar);
+            }
+        }
+        Ok(ty)
+    }
+
+    // params = ")" | "void" ")" | param ("," param)* ("," "...")? ")"
+    //
+    // Returns the parameter types, and whether the function is variadic.
+    // The parameters are declared as locals of the current function.
+    fn params(&mut self) -> Result<(Vec<Type>, bool), CompileError> {
+        if self.consume(P::RParen) {
+            return Ok((vec![], true));
+        }
+        if self.tokens[self.pos].is_kw(Kw::Void) && self.tokens[self.pos + 1].is(P::RParen) {
+            self.pos += 2;
+            return Ok((vec![], false));
+        }
+
+        let mut params = vec![self.param()?];
+        let mut is_variadic = false;
+        while self.consume(P::Comma) {
+            if self.consume(P::Ellipsis) {
+                is_variadic = true;
+                break;
+            }
+            params.push(self.param()?);
+        }
+        self.expect(P::RParen)?;
+
+        Ok((params, is_variadic))
+    }
+
+    // function = declarator "(" params ("{" stmt* "}" | ";")
+    //
+    // Returns None for a declaration without a body.
+    fn function(&mut self, base: Type) -> Result<Option<Function>, CompileError> {
+        let (return_ty, name, span) = self.declarator(base)?;
+        check_return_type(&return_ty, span)?;
+
+        // the parameters are in the same scope as the outermost block of the body
+        self.temp_locals = vec![];
+        self.enter_scope();
+
+        self.expect(P::LParen)?;
+        let (params, is_variadic) = self.params()?;
+        let ty = Type::func_type(return_ty, params, is_variadic);
+        self.declare_function(&name, &ty, span)?;
+
+        if self.consume(P::Semi) {
+            self.leave_scope();
+            return Ok(None);
+        }
+        if self.functions.iter().any(|func| func.name == name) {
+            return Err(CompileError::new(
+                ErrorKind::Redeclaration,
+                span,
+                format!("redefinition of '{}'", name),
+            ));
+        }
+
+        let paramnum = self.temp_locals.len();
+
+        // a variadic function spills the argument registers to a save area
+        // that va_arg reads from
+        self.va_area = None;
+        if ty.is_variadic && !ty.is_unprototyped() {
+            let ty = Type::new_char().array_of(VA_AREA_SIZE);
+            let lvar = LVar::new_lvar(paramnum, ty, "__va_area__".to_string());
+            self.temp_locals.push(lvar.clone());
+            self.va_area = Some(lvar);
+        }
+
+        let mut func = Function {
+            ty,
+            name,
+            paramnum,
+            locals: vec![],
+            va_area: self.va_area.clone(),
+            body: vec![],
+            is_static: false,
+        };
+        self.func_ty = func.ty.clone();
+        self.expect(P::LBrace)?;
+
+        self.labels = vec![];
+        self.gotos = vec![];
+        func.body = self.compound_stmt()?;
+        func.locals = std::mem::take(&mut self.temp_locals);
+        self.leave_scope();
+
+        for (label, span) in std::mem::take(&mut self.gotos) {
+            if !self.labels.contains(&label) {
+                return Err(CompileError::new(
+                    ErrorKind::Undeclared,
+                    span,
+                    format!("label '{}' used but not defined", label),
+                ));
+            }
+        }
+
+        Ok(Some(func))
+    }
+
+    // Lays out a static initializer as the bytes of the global's initial
+    // data, recording the slots that hold addresses as relocations.
+    fn write_gvar_data(
+        &self,
+        init: &Initializer,
+        buf: &mut [u8],
+        offset: usize,
+        relocs: &mut Vec<Reloc>,
+    ) -> Result<(), CompileError> {
+        if init.ty.kind == TypeKind::TyArr {
+            let size = init.ty.ptr_to.as_ref().unwrap().size;
+            for (i, child) in init.children.iter().enumerate() {
+                self.write_gvar_data(child, buf, offset + size * i, relocs)?;
+            }
+            return Ok(());
+        }
+        if init.ty.is_struct() && init.expr.is_none() {
+            let members = init.ty.members.iter().zip(&init.children);
+            for (member, child) in members.take(init.initialized_members()) {
+                self.write_gvar_data(child, buf, offset + member.offset, relocs)?;
+            }
+            return Ok(());
+        }
+
+        let expr = match &init.expr {
+            Some(expr) => expr,
+            None => return Ok(()),
+        };
+        if init.ty.is_flonum() {
+            let val = eval_double(expr)?;
+            let bytes = match init.ty.kind {
+                TypeKind::TyFloat => (val as f32).to_le_bytes().to_vec(),
+                _ => val.to_le_bytes().to_vec(),
+            };
+            buf[offset..offset + init.ty.size].copy_from_slice(&bytes);
+            return Ok(());
+        }
+
+        let mut label = None;
+        let val = if is_flonum(expr) {
+            // converted as a cast, which handles values too big for an i64
+            eval(&Node::new_cast(expr.clone(), init.ty.clone()), None)?
+        } else {
+            eval(expr, Some(&mut label))?
+        };
+        match label {
+            Some(label) if init.ty.size == 8 => relocs.push(Reloc {
+                offset,
+                label,
+                addend: val,
+            }),
+            Some(_) => {
+                return Err(CompileError::new(
+                    ErrorKind::NotConstant,
+                    expr.span,
+                    "initializer element is not computable at load time".to_string(),
+                ))
+            }
+            None => {
+                let bytes = val.to_le_bytes();
+                buf[offset..offset + init.ty.size].copy_from_slice(&bytes[..init.ty.size]);
+            }
+        }
+
+        Ok(())
+    }
+
+    // global_var = (declarator ("=" initializer)? ("," declarator ("=" initializer)?)*)? ";"
+    fn global_var(&mut self, base: Type, attr: &VarAttr) -> Result<(), CompileError> {
+        // a declaration of only a struct tag
+        if self.consume(P::Semi) {
+            return Ok(());
+        }
+
+        loop {
+            let (ty, name, span) = self.declarator(base.clone())?;
+            if ty.kind == TypeKind::TyVoid {
+                return Err(CompileError::new(
+                    ErrorKind::TypeMismatch,
+                    span,
+                    format!("variable '{}' declared void", name),
+                ));
+            }
+            let mut global = Global {
+                gvar: GVar { name, ty },
+                data: None,
+                relocs: vec![],
+                is_static: attr.is_static,
+            };
+
+            if self.consume(P::Assign) {
+                // the name is in scope from its declarator on, and stays
+                // declared even if the initializer has an error
+                self.scopes[0]
+                    .vars
+                    .entry(global.gvar.name.clone())
+                    .or_insert_with(|| VarScope::Global(global.gvar.clone()));
+                self.gvar_initializer(&mut global)?;
+            }
+            self.declare_global(global, attr.is_extern, span)?;
+
+            if !self.consume(P::Comma) {
+                break;
+            }
+        }
+
+        self.expect(P::Semi)
+    }
+
+    // Parses the initializer of a global or static variable into its data.
+    fn gvar_initializer(&mut self, global: &mut Global) -> Result<(), CompileError> {
+        let init = self.initializer(&global.gvar.ty)?;
+        global.gvar.ty = init.ty.clone();
+
+        let mut data = vec![0; global.gvar.ty.size];
+        self.write_gvar_data(&init, &mut data, 0, &mut global.relocs)?;
+        global.data = Some(data);
+        Ok(())
+    }
+
+    // Looks ahead to tell a function definition from a global variable.
+    fn is_function(&mut self, base: &Type) -> bool {
+        let pos = self.pos;
+        let found = self.declarator(base.clone()).is_ok() && self.tokens[self.pos].is(P::LParen);
+        self.pos = pos;
+        found
+    }
+
+    // toplevel = basetype (function | global_var | typedef)
+    fn toplevel(&mut self) -> Result<(), CompileError> {
+        let span = self.span();
+        let typed = self.is_typename();
+        let mut attr = VarAttr::default();
+        let base = self.basetype(Some(&mut attr))?;
+        if attr.is_typedef {
+            return self.parse_typedef(base);
+        }
+
+        if self.is_function(&base) {
+            if let Some(mut func) = self.function(base)? {
+                func.is_static = attr.is_static;
+                self.functions.push(func);
+            }
+            return Ok(());
+        }
+        if !typed {
+            return Err(CompileError::new(
+                ErrorKind::UnexpectedToken,
+                span,
+                format!("expected type name but got {}", self.current()),
+            ));
+        }
+        self.global_var(base, &attr)
+    }
+
+    // program = toplevel*
+    //
+    // Syntax errors are recovered from at statement and function boundaries,
+    // so that every error in the input is collected into diagnostics.
+    pub fn program(&mut self) -> Result<(), Vec<CompileError>> {
         while self.tokens[self.pos].kind != TokenKind::TkEof {
-            funcs.push(self.function());
+            match self.toplevel() {
+                Ok(()) => {}
+                Err(err) => {
+                    if err.kind == ErrorKind::TooManyErrors {
+                        self.diagnostics.push(err);
+                        break;
+                    }
+                    if self.recover(err).is_err() {
+                        break;
+                    }
+                    // drop the scopes of a function that failed to parse
+                    self.scopes.truncate(1);
+                    self.skip_toplevel();
+                }
+            }
+        }
+
+        if !self.diagnostics.is_empty() {
+            return Err(std::mem::take(&mut self.diagnostics));
         }
 
-        self.functions = funcs;
+        Ok(())
     }
 
     pub fn new(tokens: &'a Vec<Token>) -> Self {
+        let mut file_scope = Scope::default();
+        file_scope.vars.insert(
+            "va_list".to_string(),
+            VarScope::Typedef(Type::new_va_list()),
+        );
+
         Self {
-            tokens: tokens,
+            tokens,
             pos: 0,
             temp_locals: vec![],
+            scopes: vec![file_scope],
+            func_ty: Type::default(),
+            va_area: None,
+            loop_depth: 0,
+            switches: vec![],
+            labels: vec![],
+            gotos: vec![],
             functions: vec![],
+            globals: vec![],
+            strings: vec![],
+            diagnostics: vec![],
+            max_errors: 0,
+        }
+    }
+
+    // Records a recovered error. Once max_errors errors have been recorded,
+    // returns TooManyErrors so that the parser gives up.
+    fn recover(&mut self, err: CompileError) -> Result<(), CompileError> {
+        if err.kind == ErrorKind::TooManyErrors {
+            return Err(err);
+        }
+
+        let span = err.span;
+        self.diagnostics.push(err);
+
+        if self.max_errors != 0 && self.diagnostics.len() >= self.max_errors {
+            return Err(CompileError::new(
+                ErrorKind::TooManyErrors,
+                span,
+                format!(
+                    "compilation terminated due to -fmax-errors={}.",
+                    self.max_errors
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Skips tokens up to and including the ";" that ends the current
+    // statement, or up to the "}" that closes the enclosing block.
+    fn skip_stmt(&mut self) {
+        let mut depth = 0;
+
+        loop {
+            let tok = &self.tokens[self.pos];
+            if tok.kind == TokenKind::TkEof {
+                return;
+            }
+            match tok.kind {
+                TokenKind::TkPunct(P::Semi) if depth == 0 => {
+                    self.pos += 1;
+                    return;
+                }
+                TokenKind::TkPunct(P::RBrace) if depth == 0 => return,
+                TokenKind::TkPunct(P::LBrace) => depth += 1,
+                TokenKind::TkPunct(P::RBrace) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos += 1;
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
+    // Skips tokens up to and including the "}" that closes the current
+    // function body, or the ";" that ends the current global declaration.
+    fn skip_toplevel(&mut self) {
+        let mut depth = 0;
+
+        loop {
+            let tok = &self.tokens[self.pos];
+            if tok.kind == TokenKind::TkEof {
+                return;
+            }
+            self.pos += 1;
+            match tok.kind {
+                TokenKind::TkPunct(P::Semi) if depth == 0 => return,
+                TokenKind::TkPunct(P::LBrace) => depth += 1,
+                TokenKind::TkPunct(P::RBrace) if depth <= 1 => return,
+                TokenKind::TkPunct(P::RBrace) => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    fn span(&self) -> Span {
+        self.tokens[self.pos].span
+    }
+
+    // the source text of the current token, for diagnostics
+    fn current(&self) -> &str {
+        let tok = &self.tokens[self.pos];
+        if tok.kind == TokenKind::TkEof {
+            return "end of input";
         }
+        &tok.op
     }
 
-    fn consume(&mut self, op: &str) -> bool {
-        if &self.tokens[self.pos].op == op {
+    fn consume(&mut self, p: P) -> bool {
+        if self.tokens[self.pos].is(p) {
             self.pos += 1;
             return true;
         }
@@ -595,49 +2972,409 @@ impl<'a> Parser<'a> {
         false
     }
 
-    fn basetype(&mut self) -> Type {
-        let mut ty = Type {
-            ..Default::default()
+    fn consume_kw(&mut self, kw: Kw) -> bool {
+        if self.tokens[self.pos].is_kw(kw) {
+            self.pos += 1;
+            return true;
+        }
+
+        false
+    }
+
+    fn is_typename(&self) -> bool {
+        self.is_typename_at(self.pos)
+    }
+
+    fn is_typename_at(&self, pos: usize) -> bool {
+        let tok = &self.tokens[pos];
+        [
+            Kw::Typedef,
+            Kw::Extern,
+            Kw::Static,
+            Kw::Struct,
+            Kw::Union,
+            Kw::Enum,
+        ]
+        .iter()
+        .any(|&kw| tok.is_kw(kw))
+            || QUALIFIERS.iter().any(|&kw| tok.is_kw(kw))
+            || IGNORED_SPECIFIERS.iter().any(|&kw| tok.is_kw(kw))
+            || TYPE_SPECIFIERS.iter().any(|&(kw, _)| tok.is_kw(kw))
+            || self.find_typedef(tok).is_some()
+    }
+
+    fn find_typedef(&self, tok: &Token) -> Option<&Type> {
+        if tok.kind != TokenKind::TkIdent {
+            return None;
+        }
+        match self.find_var(&tok.op) {
+            Some(VarScope::Typedef(ty)) => Some(ty),
+            _ => None,
+        }
+    }
+
+    // Declares a typedef name or an enumerator in the innermost scope.
+    fn push_scope_var(
+        &mut self,
+        name: String,
+        var: VarScope,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        let scope = self.scopes.last().unwrap();
+        let redeclared = match (scope.vars.get(&name), &var) {
+            // C11 allows a typedef to be repeated with the same type
+            (Some(VarScope::Typedef(prev)), VarScope::Typedef(ty)) => prev != ty,
+            (Some(_), _) => true,
+            (None, _) => false,
         };
-        if self.consume("int") {
-            ty = Type::new_int();
+        if redeclared {
+            self.recover(CompileError::new(
+                ErrorKind::Redeclaration,
+                span,
+                format!("redeclaration of '{}'", name),
+            ))?;
         }
 
-        while self.consume("*") {
-            ty = ty.pointer_to();
+        self.scopes.last_mut().unwrap().vars.insert(name, var);
+        Ok(())
+    }
+
+    // enum_specifier = ident? "{" enum_list? "}" | ident
+    // enum_list = ident ("=" const_expr)? ("," ident ("=" const_expr)?)* ","?
+    fn enum_specifier(&mut self) -> Result<Type, CompileError> {
+        let span = self.span();
+        let tag = match self.tokens[self.pos].kind {
+            TokenKind::TkIdent => Some(self.expect_ident()?),
+            _ => None,
+        };
+
+        if let Some(tag) = &tag {
+            if !self.tokens[self.pos].is(P::LBrace) {
+                return match self.find_tag(tag) {
+                    Some(ty) if ty.kind == TypeKind::TyEnum => Ok(ty.clone()),
+                    _ => Err(CompileError::new(
+                        ErrorKind::Undeclared,
+                        span,
+                        format!("unknown enum type '{}'", tag),
+                    )),
+                };
+            }
+        }
+
+        self.expect(P::LBrace)?;
+        let ty = Type::new_enum();
+        let mut val = 0;
+        let mut first = true;
+        while !self.consume_end() {
+            if !first {
+                self.expect(P::Comma)?;
+            }
+            first = false;
+
+            let span = self.span();
+            let name = self.expect_ident()?;
+            if self.consume(P::Assign) {
+                val = self.const_expr()?;
+            }
+            self.push_scope_var(name, VarScope::EnumConst(val), span)?;
+            val += 1;
+        }
+
+        if let Some(tag) = tag {
+            self.push_tag(tag, ty.clone());
+        }
+        Ok(ty)
+    }
+
+    // typedef = declarator ("," declarator)* ";"
+    fn parse_typedef(&mut self, base: Type) -> Result<(), CompileError> {
+        if self.consume(P::Semi) {
+            return Ok(());
+        }
+
+        loop {
+            let (ty, name, span) = self.declarator(base.clone())?;
+            self.push_scope_var(name, VarScope::Typedef(ty), span)?;
+            if !self.consume(P::Comma) {
+                break;
+            }
+        }
+
+        self.expect(P::Semi)
+    }
+
+    // const_expr = conditional
+    //
+    // An expression that is evaluated while parsing, such as an array length.
+    fn const_expr(&mut self) -> Result<i64, CompileError> {
+        let mut node = self.conditional()?;
+        node.check_type();
+        eval(&node, None)
+    }
+
+    // struct_decl = ident? ("{" (basetype declarator ("," declarator)* ";")* "}")?
+    fn struct_decl(&mut self, is_union: bool) -> Result<Type, CompileError> {
+        let span = self.span();
+        let tag = match self.tokens[self.pos].kind {
+            TokenKind::TkIdent => Some(self.expect_ident()?),
+            _ => None,
+        };
+
+        if let Some(tag) = &tag {
+            if !self.tokens[self.pos].is(P::LBrace) {
+                // a reference to a struct declared earlier, or a forward declaration
+                if let Some(ty) = self.find_tag(tag) {
+                    return Ok(ty.clone());
+                }
+                let ty = Type::incomplete_struct(tag.clone(), is_union);
+                self.push_tag(tag.clone(), ty.clone());
+                return Ok(ty);
+            }
+            // members may point to the struct being defined
+            let scope = self.scopes.last().unwrap();
+            if !scope.tags.contains_key(tag) {
+                self.push_tag(tag.clone(), Type::incomplete_struct(tag.clone(), is_union));
+            }
+        }
+
+        self.expect(P::LBrace)?;
+        let mut members: Vec<Member> = vec![];
+        while !self.consume(P::RBrace) {
+            let base = self.basetype(None)?;
+            loop {
+                let (ty, name, span) = self.declarator(base.clone())?;
+                if members.iter().any(|m| m.name == name) {
+                    return Err(CompileError::new(
+                        ErrorKind::Redeclaration,
+                        span,
+                        format!("duplicate member '{}'", name),
+                    ));
+                }
+                if ty.incomplete {
+                    return Err(CompileError::new(
+                        ErrorKind::TypeMismatch,
+                        span,
+                        format!("member '{}' has incomplete type", name),
+                    ));
+                }
+                members.push(Member {
+                    name,
+                    ty,
+                    offset: 0,
+                });
+                if !self.consume(P::Comma) {
+                    break;
+                }
+            }
+            self.expect(P::Semi)?;
+        }
+
+        let ty = Type::struct_of(tag.clone(), members, is_union);
+        if let Some(tag) = tag {
+            let scope = self.scopes.last().unwrap();
+            if scope.tags.get(&tag).is_some_and(|prev| !prev.incomplete) {
+                return Err(CompileError::new(
+                    ErrorKind::Redeclaration,
+                    span,
+                    format!("redefinition of '{}'", tag),
+                ));
+            }
+            self.push_tag(tag, ty.clone());
+        }
+
+        Ok(ty)
+    }
+
+    // typename = basetype pointers type_suffix
+    fn typename(&mut self) -> Result<Type, CompileError> {
+        let base = self.basetype(None)?;
+        let ty = self.complete_struct(&base);
+        let ty = self.pointers(ty);
+        self.type_suffix(ty)
+    }
+
+    // basetype = ("typedef" | "extern" | "static" | "const" | "volatile" | "restrict"
+    //             | "inline" | "_Noreturn" | "register" | "auto"
+    //             | "void" | "_Bool" | "char" | "short" | "int" | "long" | "float"
+    //             | "double" | "signed" | "unsigned" | ("struct" | "union") struct_decl | "enum" enum_specifier
+    //             | typedef_name)*
+    //
+    // The specifiers may be written in any order, as in "long unsigned int",
+    // so they are counted and the combination is looked up at the end.
+    // Storage-class specifiers are only accepted where attr is given.
+    fn basetype(&mut self, mut attr: Option<&mut VarAttr>) -> Result<Type, CompileError> {
+        let span = self.span();
+        let mut counter = 0;
+        let mut ty = Type::default();
+
+        loop {
+            if QUALIFIERS.iter().any(|&kw| self.consume_kw(kw))
+                || IGNORED_SPECIFIERS.iter().any(|&kw| self.consume_kw(kw))
+            {
+                continue;
+            }
+
+            let tok = &self.tokens[self.pos];
+            if tok.is_kw(Kw::Typedef) || tok.is_kw(Kw::Extern) || tok.is_kw(Kw::Static) {
+                match attr.as_deref_mut() {
+                    Some(attr) if tok.is_kw(Kw::Typedef) => attr.is_typedef = true,
+                    Some(attr) if tok.is_kw(Kw::Extern) => attr.is_extern = true,
+                    Some(attr) => attr.is_static = true,
+                    None => {
+                        return Err(CompileError::new(
+                            ErrorKind::UnexpectedToken,
+                            self.span(),
+                            "storage class specified in this context".to_string(),
+                        ))
+                    }
+                }
+                self.pos += 1;
+                continue;
+            }
+
+            if self.tokens[self.pos].is_kw(Kw::Struct) || self.tokens[self.pos].is_kw(Kw::Union) {
+                let is_union = self.tokens[self.pos].is_kw(Kw::Union);
+                self.pos += 1;
+                ty = self.struct_decl(is_union)?;
+                counter += OTHER;
+                continue;
+            }
+            if self.consume_kw(Kw::Enum) {
+                ty = self.enum_specifier()?;
+                counter += OTHER;
+                continue;
+            }
+
+            // an identifier is a typedef name only where no type was given
+            // yet, so that in "typedef int T; T T;" the second T is declared
+            if counter == 0 {
+                if let Some(found) = self.find_typedef(&self.tokens[self.pos]) {
+                    ty = found.clone();
+                    counter += OTHER;
+                    self.pos += 1;
+                    continue;
+                }
+            }
+
+            match TYPE_SPECIFIERS
+                .iter()
+                .find(|&&(kw, _)| self.tokens[self.pos].is_kw(kw))
+            {
+                Some((_, n)) => counter += n,
+                None => break,
+            }
+            self.pos += 1;
         }
 
+        let ty = match counter {
+            0 => Type::default(),
+            OTHER => ty,
+            VOID => Type::new_void(),
+            FLOAT => Type::new_float(),
+            DOUBLE => Type::new_double(),
+            BOOL => Type::new_bool(),
+            CHAR | SIGNED_CHAR => Type::new_char(),
+            UNSIGNED_CHAR => Type::new_uchar(),
+            SHORT | SHORT_INT | SIGNED_SHORT | SIGNED_SHORT_INT => Type::new_short(),
+            UNSIGNED_SHORT | UNSIGNED_SHORT_INT => Type::new_ushort(),
+            INT | SIGNED | SIGNED_INT => Type::new_int(),
+            UNSIGNED | UNSIGNED_INT => Type::new_uint(),
+            LONG | LONG_INT | LONG_LONG | LONG_LONG_INT | SIGNED_LONG | SIGNED_LONG_INT
+            | SIGNED_LONG_LONG | SIGNED_LONG_LONG_INT => Type::new_long(),
+            UNSIGNED_LONG | UNSIGNED_LONG_INT | UNSIGNED_LONG_LONG | UNSIGNED_LONG_LONG_INT => {
+                Type::new_ulong()
+            }
+            _ => {
+                return Err(CompileError::new(
+                    ErrorKind::UnexpectedToken,
+                    span,
+                    "invalid combination of type specifiers".to_string(),
+                ))
+            }
+        };
+
+        Ok(ty)
+    }
+
+    // pointers = ("*" qualifier*)*
+    //
+    // The qualifiers are accepted but not enforced: const objects may still
+    // be assigned to, and volatile ones are always loaded from memory anyway.
+    fn pointers(&mut self, mut ty: Type) -> Type {
+        while self.consume(P::Star) {
+            ty = ty.pointer_to();
+            while QUALIFIERS.iter().any(|&kw| self.consume_kw(kw)) {}
+        }
         ty
     }
 
-    fn type_suffix(&mut self, mut base: Type) -> Type {
-        if !self.consume("[") {
-            return base;
+    // declarator = pointers ident type_suffix
+    fn declarator(&mut self, ty: Type) -> Result<(Type, String, Span), CompileError> {
+        let ty = self.complete_struct(&ty);
+        let ty = self.pointers(ty);
+        let span = self.span();
+        let name = self.expect_ident()?;
+        let ty = self.type_suffix(ty)?;
+
+        Ok((ty, name, span))
+    }
+
+    // type_suffix = ("[" const_expr? "]" type_suffix)?
+    fn type_suffix(&mut self, mut base: Type) -> Result<Type, CompileError> {
+        if !self.consume(P::LBracket) {
+            return Ok(base);
         }
-        let n = self.tokens[self.pos].val;
-        self.pos += 1;
-        self.expect("]");
-        base = self.type_suffix(base);
+        if self.consume(P::RBracket) {
+            base = self.type_suffix(base)?;
+            return Ok(base.incomplete_array_of());
+        }
+        let span = self.span();
+        let n = self.const_expr()?;
+        if n < 0 {
+            return Err(CompileError::new(
+                ErrorKind::TypeMismatch,
+                span,
+                "size of array is negative".to_string(),
+            ));
+        }
+        self.expect(P::RBracket)?;
+        base = self.type_suffix(base)?;
+
+        Ok(base.array_of(n as usize))
+    }
+
+    fn expect(&mut self, p: P) -> Result<(), CompileError> {
+        self.expect_kind(TokenKind::TkPunct(p), p.as_str())
+    }
 
-        return base.array_of(n as usize);
+    fn expect_kw(&mut self, kw: Kw) -> Result<(), CompileError> {
+        self.expect_kind(TokenKind::TkKeyword(kw), kw.as_str())
     }
 
-    fn expect(&mut self, op: &str) {
-        if &self.tokens[self.pos].op != op {
-            eprintln!("expected {} but got {}", op, self.tokens[self.pos].op);
-            process::exit(1);
+    fn expect_kind(&mut self, kind: TokenKind, spelling: &str) -> Result<(), CompileError> {
+        if self.tokens[self.pos].kind != kind {
+            return Err(CompileError::new(
+                ErrorKind::UnexpectedToken,
+                self.span(),
+                format!("expected {} but got {}", spelling, self.current()),
+            ));
         }
         self.pos += 1;
+
+        Ok(())
     }
 
-    fn expect_ident(&mut self) -> String {
+    fn expect_ident(&mut self) -> Result<String, CompileError> {
         if self.tokens[self.pos].kind != TokenKind::TkIdent {
-            eprintln!("expected identifier but got {}", self.tokens[self.pos].op);
-            process::exit(1);
+            return Err(CompileError::new(
+                ErrorKind::UnexpectedToken,
+                self.span(),
+                format!("expected identifier but got {}", self.current()),
+            ));
         }
         let ident = &self.tokens[self.pos].op;
         self.pos += 1;
 
-        ident.to_string()
+        Ok(ident.to_string())
     }
 }
@@ -0,0 +1,1006 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{CompileError, ErrorKind, Source};
+use crate::tokenize::{tokenize, Span, Symbol, Token, TokenKind, P};
+
+static SYSTEM_INCLUDE_PATHS: [&str; 3] = [
+    "/usr/local/include",
+    "/usr/include/x86_64-linux-gnu",
+    "/usr/include",
+];
+
+// Headers whose contents are built into the compiler, used when no file of
+// the same name is found in the include paths.
+static BUILTIN_HEADERS: [(&str, &str); 1] = [("stdarg.h", "")];
+
+// The deepest nesting of #include allowed, as in gcc, so that a file that
+// includes itself is reported rather than expanded forever.
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+#[derive(Clone)]
+struct Macro {
+    params: Option<Vec<Symbol>>, // None for object-like macros
+    variadic: bool,
+    body: Vec<Token>,
+}
+
+// State of one #if ... #endif group.
+struct Cond {
+    span: Span,
+    taken: bool, // some branch of the group has already been included
+    in_else: bool,
+}
+
+pub struct Preprocessor {
+    pub files: Vec<Source>,
+    pub include_paths: Vec<String>,
+    // how deeply each of files is nested in #includes
+    depths: Vec<usize>,
+    macros: HashMap<Symbol, Macro>,
+    conds: Vec<Cond>,
+    // tokens still to be read, in reverse order so that the next one can be
+    // popped and macro expansions pushed back
+    input: Vec<Token>,
+}
+
+fn error(span: Span, msg: String) -> CompileError {
+    CompileError::new(ErrorKind::Preprocessor, span, msg)
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        let mut pp = Self {
+            files: vec![],
+            include_paths: vec![],
+            depths: vec![],
+            macros: HashMap::new(),
+            conds: vec![],
+            input: vec![],
+        };
+
+        for (name, val) in &[
+            ("__STDC__", "1"),
+            ("__STDC_VERSION__", "201112"),
+            ("__STDC_HOSTED__", "1"),
+            ("__x86_64__", "1"),
+            ("__LP64__", "1"),
+            ("__linux__", "1"),
+            ("__mmcc2__", "1"),
+        ] {
+            pp.define(name, val).unwrap();
+        }
+
+        pp
+    }
+
+    fn add_file(&mut self, src: Source, depth: usize) -> Result<Vec<Token>, CompileError> {
+        self.files.push(src);
+        self.depths.push(depth);
+        let file = self.files.len() - 1;
+        tokenize(&self.files[file], file)
+    }
+
+    // Defines a macro as -D name=val does.
+    pub fn define(&mut self, name: &str, val: &str) -> Result<(), CompileError> {
+        let src = Source::new("<command-line>".to_string(), val.to_string());
+        let mut body = self.add_file(src, 0)?;
+        body.pop();
+        if let Some(tok) = body.first_mut() {
+            tok.has_space = false;
+        }
+
+        self.macros.insert(
+            Symbol::intern(name),
+            Macro {
+                params: None,
+                variadic: false,
+                body,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn undef(&mut self, name: &str) {
+        self.macros.remove(&Symbol::intern(name));
+    }
+
+    // Runs the preprocessor over a source file and returns its tokens, with
+    // directives executed and macros expanded.
+    pub fn preprocess(&mut self, src: Source) -> Result<Vec<Token>, CompileError> {
+        let mut tokens = self.add_file(src, 0)?;
+        let eof = tokens.pop().unwrap();
+        self.push_tokens(tokens);
+
+        let mut out = self.expand_all()?;
+
+        if let Some(cond) = self.conds.last() {
+            return Err(error(
+                cond.span,
+                "unterminated conditional directive".to_string(),
+            ));
+        }
+
+        out.push(eof);
+        Ok(out)
+    }
+
+    fn push_tokens(&mut self, tokens: Vec<Token>) {
+        self.input.extend(tokens.into_iter().rev());
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.input.last()
+    }
+
+    // Reads the rest of the current line.
+    fn read_line(&mut self) -> Vec<Token> {
+        let mut line = vec![];
+        while let Some(tok) = self.peek() {
+            if tok.bol {
+                break;
+            }
+            line.push(self.input.pop().unwrap());
+        }
+        line
+    }
+
+    // Expands everything left in the input.
+    fn expand_all(&mut self) -> Result<Vec<Token>, CompileError> {
+        let mut out = vec![];
+
+        while let Some(tok) = self.input.pop() {
+            if tok.is(P::Hash) && tok.bol {
+                self.directive(tok)?;
+                continue;
+            }
+            if self.expand_macro(&tok)? {
+                continue;
+            }
+            out.push(tok);
+        }
+
+        Ok(out)
+    }
+
+    // Fully expands a list of tokens, such as a macro argument, without
+    // touching the rest of the input.
+    fn expand_tokens(&mut self, tokens: Vec<Token>) -> Result<Vec<Token>, CompileError> {
+        let saved = std::mem::take(&mut self.input);
+        self.push_tokens(tokens);
+
+        let mut out = vec![];
+        while let Some(tok) = self.input.pop() {
+            if !self.expand_macro(&tok)? {
+                out.push(tok);
+            }
+        }
+
+        self.input = saved;
+        Ok(out)
+    }
+
+    // If tok names a macro, pushes its expansion back to the input and
+    // returns true.
+    fn expand_macro(&mut self, tok: &Token) -> Result<bool, CompileError> {
+        if !tok.is_ident() || tok.hideset.contains(&tok.op) {
+            return Ok(false);
+        }
+
+        if tok.op == "__FILE__" {
+            let name = self.files[tok.span.file].name.clone();
+            self.push_expansion(tok, vec![Token::new_token_str(&name, tok.span)]);
+            return Ok(true);
+        }
+        if tok.op == "__LINE__" {
+            self.push_expansion(
+                tok,
+                vec![Token::new_token_num(tok.span.line as u64, tok.span)],
+            );
+            return Ok(true);
+        }
+
+        let mac = match self.macros.get(&tok.op) {
+            Some(mac) => mac.clone(),
+            None => return Ok(false),
+        };
+
+        let mut hideset = tok.hideset.clone();
+        hideset.push(tok.op);
+
+        let params = match &mac.params {
+            None => {
+                let body = mac.body.into_iter().map(|t| Token {
+                    span: tok.span,
+                    hideset: union(&t.hideset, &hideset),
+                    ..t
+                });
+                self.push_expansion(tok, body.collect());
+                return Ok(true);
+            }
+            Some(params) => params,
+        };
+
+        // a function-like macro name not followed by "(" is left alone
+        match self.peek() {
+            Some(next) if next.is(P::LParen) => {}
+            _ => return Ok(false),
+        }
+        self.input.pop();
+
+        let (args, rparen) = self.read_args(tok, params.len(), mac.variadic)?;
+
+        // the expansion hides only what both the name and ")" hide
+        let mut hideset: Vec<Symbol> = tok
+            .hideset
+            .iter()
+            .filter(|name| rparen.hideset.contains(name))
+            .cloned()
+            .collect();
+        hideset.push(tok.op);
+
+        let body = self.substitute(&mac, params, args)?;
+        let body = body.into_iter().map(|t| Token {
+            span: tok.span,
+            hideset: union(&t.hideset, &hideset),
+            ..t
+        });
+        self.push_expansion(tok, body.collect());
+        Ok(true)
+    }
+
+    // The first token of an expansion takes the place of the macro name.
+    fn push_expansion(&mut self, tok: &Token, mut tokens: Vec<Token>) {
+        if let Some(first) = tokens.first_mut() {
+            first.bol = tok.bol;
+            first.has_space = tok.has_space;
+        }
+        self.push_tokens(tokens);
+    }
+
+    // Reads the arguments of a function-like macro call up to the closing
+    // ")". The variadic arguments, commas included, are the last argument.
+    fn read_args(
+        &mut self,
+        name: &Token,
+        nparams: usize,
+        variadic: bool,
+    ) -> Result<(Vec<Vec<Token>>, Token), CompileError> {
+        let mut args = vec![vec![]];
+        let mut depth = 0;
+
+        loop {
+            let tok = match self.input.pop() {
+                Some(tok) => tok,
+                None => {
+                    return Err(error(
+                        name.span,
+                        format!("unterminated argument list invoking macro '{}'", name.op),
+                    ))
+                }
+            };
+
+            if depth == 0 && tok.is(P::RParen) {
+                if nparams == 0 && args.len() == 1 && args[0].is_empty() {
+                    args.clear();
+                }
+                if args.len() < nparams || (!variadic && args.len() > nparams) {
+                    return Err(error(
+                        name.span,
+                        format!(
+                            "macro '{}' requires {} arguments, but {} given",
+                            name.op,
+                            nparams,
+                            args.len()
+                        ),
+                    ));
+                }
+                return Ok((args, tok));
+            }
+
+            if depth == 0 && tok.is(P::Comma) && !(variadic && args.len() == nparams + 1) {
+                args.push(vec![]);
+                continue;
+            }
+            if tok.is(P::LParen) {
+                depth += 1;
+            } else if tok.is(P::RParen) {
+                depth -= 1;
+            }
+            args.last_mut().unwrap().push(tok);
+        }
+    }
+
+    // Replaces the parameters in a macro body with its arguments, handling
+    // the # and ## operators.
+    fn substitute(
+        &mut self,
+        mac: &Macro,
+        params: &[Symbol],
+        mut args: Vec<Vec<Token>>,
+    ) -> Result<Vec<Token>, CompileError> {
+        if mac.variadic && args.len() == params.len() {
+            args.push(vec![]);
+        }
+        let arg_of = |tok: &Token| -> Option<usize> {
+            if !tok.is_ident() {
+                return None;
+            }
+            if mac.variadic && tok.op == "__VA_ARGS__" {
+                return Some(params.len());
+            }
+            params.iter().position(|p| *p == tok.op)
+        };
+
+        let body = &mac.body;
+        let mut out: Vec<Token> = vec![];
+        let mut i = 0;
+        while i < body.len() {
+            let tok = &body[i];
+
+            // # param
+            if tok.is(P::Hash) {
+                match body.get(i + 1).and_then(arg_of) {
+                    Some(n) => {
+                        let mut s = Token::new_token_str(&spell(&args[n]), tok.span);
+                        s.has_space = tok.has_space;
+                        out.push(s);
+                        i += 2;
+                        continue;
+                    }
+                    None => {
+                        return Err(error(
+                            tok.span,
+                            "'#' is not followed by a macro parameter".to_string(),
+                        ))
+                    }
+                }
+            }
+
+            // x ## y
+            if tok.is(P::HashHash) {
+                if out.is_empty() || i + 1 == body.len() {
+                    return Err(error(
+                        tok.span,
+                        "'##' cannot appear at either end of a macro expansion".to_string(),
+                    ));
+                }
+                let rhs = &body[i + 1];
+                let mut rhs = match arg_of(rhs) {
+                    Some(n) => args[n].clone(),
+                    None => vec![rhs.clone()],
+                };
+                i += 2;
+
+                if rhs.is_empty() {
+                    continue;
+                }
+                let lhs = out.pop().unwrap();
+                let pasted = self.paste(&lhs, &rhs[0])?;
+                out.push(pasted);
+                out.extend(rhs.drain(1..));
+                continue;
+            }
+
+            if let Some(n) = arg_of(tok) {
+                // an operand of ## is not macro-expanded
+                if body.get(i + 1).is_some_and(|t| t.is(P::HashHash)) {
+                    if args[n].is_empty() {
+                        // pasting onto an empty argument yields the other operand
+                        i += 2;
+                        if let Some(rhs) = body.get(i) {
+                            match arg_of(rhs) {
+                                Some(m) => out.extend(args[m].clone()),
+                                None => out.push(rhs.clone()),
+                            }
+                            i += 1;
+                        }
+                        continue;
+                    }
+                    out.extend(args[n].clone());
+                    i += 1;
+                    continue;
+                }
+
+                let mut expanded = self.expand_tokens(args[n].clone())?;
+                if let Some(first) = expanded.first_mut() {
+                    first.has_space = tok.has_space;
+                }
+                out.extend(expanded);
+                i += 1;
+                continue;
+            }
+
+            out.push(tok.clone());
+            i += 1;
+        }
+
+        Ok(out)
+    }
+
+    // Concatenates two tokens with ## into a single new token.
+    fn paste(&mut self, lhs: &Token, rhs: &Token) -> Result<Token, CompileError> {
+        let text = format!("{}{}", lhs.op, rhs.op);
+        let src = Source::new("<paste>".to_string(), text.clone());
+        let tokens = tokenize(&src, lhs.span.file).unwrap_or_default();
+        if tokens.len() != 2 {
+            return Err(error(
+                lhs.span,
+                format!(
+                    "pasting \"{}\" and \"{}\" does not give a valid preprocessing token",
+                    lhs.op, rhs.op
+                ),
+            ));
+        }
+
+        Ok(Token {
+            span: lhs.span,
+            bol: lhs.bol,
+            has_space: lhs.has_space,
+            ..tokens[0].clone()
+        })
+    }
+
+    fn directive(&mut self, hash: Token) -> Result<(), CompileError> {
+        let mut line = self.read_line();
+        if line.is_empty() {
+            return Ok(()); // null directive
+        }
+        let name = line.remove(0);
+
+        match name.op.as_str() {
+            "include" => self.include(&name, line),
+            "define" => self.define_directive(&name, line),
+            "undef" => {
+                match line.first() {
+                    Some(tok) if tok.is_ident() => self.undef(tok.op.as_str()),
+                    _ => return Err(error(name.span, "macro name missing".to_string())),
+                }
+                Ok(())
+            }
+            "if" => {
+                let val = self.eval_cond(&name, line)?;
+                self.push_cond(name.span, val)
+            }
+            "ifdef" | "ifndef" => {
+                let defined = match line.first() {
+                    Some(tok) if tok.is_ident() => self.macros.contains_key(&tok.op),
+                    _ => return Err(error(name.span, "macro name missing".to_string())),
+                };
+                self.push_cond(name.span, defined == (name.op == "ifdef"))
+            }
+            "elif" => {
+                match self.conds.last() {
+                    Some(cond) if !cond.in_else => {}
+                    _ => return Err(error(name.span, "#elif without #if".to_string())),
+                }
+                // this is only reached when the previous branch was taken
+                self.skip_group()
+            }
+            "else" => {
+                match self.conds.last_mut() {
+                    Some(cond) if !cond.in_else => cond.in_else = true,
+                    _ => return Err(error(name.span, "#else without #if".to_string())),
+                }
+                self.skip_group()
+            }
+            "endif" => match self.conds.pop() {
+                Some(_) => Ok(()),
+                None => Err(error(name.span, "#endif without #if".to_string())),
+            },
+            "error" => Err(error(
+                name.span,
+                format!("#error {}", spell(&line)).trim_end().to_string(),
+            )),
+            "pragma" | "line" => Ok(()),
+            _ => Err(error(
+                hash.span,
+                format!("invalid preprocessing directive #{}", name.op),
+            )),
+        }
+    }
+
+    fn push_cond(&mut self, span: Span, taken: bool) -> Result<(), CompileError> {
+        self.conds.push(Cond {
+            span,
+            taken,
+            in_else: false,
+        });
+        if taken {
+            return Ok(());
+        }
+        self.skip_group()
+    }
+
+    // Skips tokens until the #elif, #else or #endif that ends the current
+    // group, and enters the next group whose condition holds.
+    fn skip_group(&mut self) -> Result<(), CompileError> {
+        let mut depth = 0;
+
+        while let Some(tok) = self.input.pop() {
+            if !(tok.is(P::Hash) && tok.bol) {
+                continue;
+            }
+            let name = match self.peek() {
+                Some(name) if !name.bol => self.input.pop().unwrap(),
+                _ => continue,
+            };
+
+            match name.op.as_str() {
+                "if" | "ifdef" | "ifndef" => depth += 1,
+                "endif" if depth > 0 => depth -= 1,
+                "endif" => {
+                    self.read_line();
+                    self.conds.pop();
+                    return Ok(());
+                }
+                "elif" if depth == 0 => {
+                    let cond = self.conds.last().unwrap();
+                    if cond.in_else {
+                        return Err(error(name.span, "#elif after #else".to_string()));
+                    }
+                    if !cond.taken {
+                        let line = self.read_line();
+                        if self.eval_cond(&name, line)? {
+                            self.conds.last_mut().unwrap().taken = true;
+                            return Ok(());
+                        }
+                    }
+                }
+                "else" if depth == 0 => {
+                    self.read_line();
+                    let cond = self.conds.last_mut().unwrap();
+                    if cond.in_else {
+                        return Err(error(name.span, "#else after #else".to_string()));
+                    }
+                    cond.in_else = true;
+                    if !cond.taken {
+                        cond.taken = true;
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let cond = self.conds.last().unwrap();
+        Err(error(
+            cond.span,
+            "unterminated conditional directive".to_string(),
+        ))
+    }
+
+    fn include(&mut self, name: &Token, mut line: Vec<Token>) -> Result<(), CompileError> {
+        // the file name may itself come from a macro
+        if line.first().is_some_and(|tok| tok.is_ident()) {
+            line = self.expand_tokens(line)?;
+        }
+
+        let (path, quoted) = match line.first() {
+            Some(tok) if tok.kind == TokenKind::TkStr => {
+                (tok.op[1..tok.op.len() - 1].to_string(), true)
+            }
+            Some(tok) if tok.is(P::Lt) => {
+                let end = match line.iter().position(|tok| tok.is(P::Gt)) {
+                    Some(end) => end,
+                    None => return Err(error(tok.span, "expected '>'".to_string())),
+                };
+                (spell(&line[1..end]), false)
+            }
+            _ => {
+                return Err(error(
+                    name.span,
+                    "#include expects \"FILENAME\" or <FILENAME>".to_string(),
+                ))
+            }
+        };
+
+        let depth = self.depths[name.span.file] + 1;
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(error(
+                name.span,
+                format!(
+                    "#include nested depth {} exceeds maximum of {}",
+                    depth, MAX_INCLUDE_DEPTH
+                ),
+            ));
+        }
+
+        let mut dirs = vec![];
+        if quoted {
+            let current = Path::new(&self.files[name.span.file].name);
+            let dir = current.parent().unwrap_or_else(|| Path::new(""));
+            dirs.push(dir.display().to_string());
+        }
+        dirs.extend(self.include_paths.iter().cloned());
+        dirs.extend(SYSTEM_INCLUDE_PATHS.iter().map(|dir| dir.to_string()));
+
+        for dir in dirs {
+            let full = if dir.is_empty() {
+                path.clone()
+            } else {
+                format!("{}/{}", dir, path)
+            };
+            if let Ok(text) = fs::read_to_string(&full) {
+                let mut tokens = self.add_file(Source::new(full, text), depth)?;
+                tokens.pop();
+                self.push_tokens(tokens);
+                return Ok(());
+            }
+        }
+
+        if let Some((_, text)) = BUILTIN_HEADERS.iter().find(|(name, _)| *name == path) {
+            let src = Source::new(format!("<built-in>/{}", path), text.to_string());
+            let mut tokens = self.add_file(src, depth)?;
+            tokens.pop();
+            self.push_tokens(tokens);
+            return Ok(());
+        }
+
+        Err(error(
+            line[0].span,
+            format!("{}: No such file or directory", path),
+        ))
+    }
+
+    fn define_directive(&mut self, name: &Token, line: Vec<Token>) -> Result<(), CompileError> {
+        let mut rest = line.into_iter().peekable();
+        let mac_name = match rest.next() {
+            Some(tok) if tok.is_ident() => tok,
+            _ => return Err(error(name.span, "macro name missing".to_string())),
+        };
+        let missing_rparen = || {
+            error(
+                mac_name.span,
+                "missing ')' in macro parameter list".to_string(),
+            )
+        };
+
+        // a "(" right after the name starts a parameter list
+        let mut params = None;
+        let mut variadic = false;
+        if rest
+            .peek()
+            .is_some_and(|tok| tok.is(P::LParen) && !tok.has_space)
+        {
+            rest.next();
+            let mut names = vec![];
+            if rest.peek().is_some_and(|tok| tok.is(P::RParen)) {
+                rest.next();
+            } else {
+                loop {
+                    let tok = rest.next().ok_or_else(missing_rparen)?;
+                    if tok.is(P::Ellipsis) {
+                        variadic = true;
+                        match rest.next() {
+                            Some(tok) if tok.is(P::RParen) => break,
+                            _ => return Err(missing_rparen()),
+                        }
+                    }
+                    if !tok.is_ident() {
+                        return Err(error(
+                            tok.span,
+                            format!("expected parameter name but got {}", tok.op),
+                        ));
+                    }
+                    names.push(tok.op);
+
+                    match rest.next() {
+                        Some(tok) if tok.is(P::RParen) => break,
+                        Some(tok) if tok.is(P::Comma) => {}
+                        _ => return Err(missing_rparen()),
+                    }
+                }
+            }
+            params = Some(names);
+        }
+
+        let mut body: Vec<Token> = rest.collect();
+        if let Some(first) = body.first_mut() {
+            first.has_space = false;
+        }
+        self.macros.insert(
+            mac_name.op,
+            Macro {
+                params,
+                variadic,
+                body,
+            },
+        );
+        Ok(())
+    }
+
+    // Evaluates the constant expression of an #if or #elif.
+    fn eval_cond(&mut self, name: &Token, line: Vec<Token>) -> Result<bool, CompileError> {
+        // replace defined(X) and defined X with 1 or 0 before expansion
+        let mut tokens = vec![];
+        let mut i = 0;
+        while i < line.len() {
+            let tok = &line[i];
+            if tok.is_ident() && tok.op == "defined" {
+                let paren = line.get(i + 1).is_some_and(|t| t.is(P::LParen));
+                let ident = if paren { i + 2 } else { i + 1 };
+                match line.get(ident) {
+                    Some(t) if t.is_ident() => {
+                        let val = self.macros.contains_key(&t.op) as u64;
+                        tokens.push(Token::new_token_num(val, tok.span));
+                    }
+                    _ => return Err(error(tok.span, "macro name missing".to_string())),
+                }
+                i = ident + 1;
+                if paren {
+                    if !line.get(i).is_some_and(|t| t.is(P::RParen)) {
+                        return Err(error(tok.span, "missing ')' after \"defined\"".to_string()));
+                    }
+                    i += 1;
+                }
+                continue;
+            }
+            tokens.push(tok.clone());
+            i += 1;
+        }
+
+        let mut tokens = self.expand_tokens(tokens)?;
+        for tok in &mut tokens {
+            // identifiers left after expansion are 0
+            if tok.is_ident() {
+                *tok = Token::new_token_num(0, tok.span);
+            }
+        }
+        if tokens.is_empty() {
+            return Err(error(name.span, format!("#{} with no expression", name.op)));
+        }
+
+        let mut eval = Eval {
+            tokens: &tokens,
+            pos: 0,
+            skipping: false,
+        };
+        let val = eval.conditional()?;
+        if eval.pos != tokens.len() {
+            return Err(error(
+                tokens[eval.pos].span,
+                format!("missing binary operator before {}", tokens[eval.pos].op),
+            ));
+        }
+
+        Ok(val.val != 0)
+    }
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Preprocessor::new()
+    }
+}
+
+fn union(a: &[Symbol], b: &[Symbol]) -> Vec<Symbol> {
+    let mut set = a.to_vec();
+    for name in b {
+        if !set.contains(name) {
+            set.push(*name);
+        }
+    }
+    set
+}
+
+// Joins token spellings the way they were spaced in the source.
+fn spell(tokens: &[Token]) -> String {
+    let mut s = String::new();
+    for (i, tok) in tokens.iter().enumerate() {
+        if i > 0 && tok.has_space {
+            s.push(' ');
+        }
+        s.push_str(&tok.op);
+    }
+    s
+}
+
+// Formats preprocessed tokens as source text, for -E.
+pub fn format_tokens(tokens: &[Token]) -> String {
+    let mut s = String::new();
+    for tok in tokens {
+        if tok.kind == TokenKind::TkEof {
+            break;
+        }
+        if s.is_empty() {
+        } else if tok.bol {
+            s.push('\n');
+        } else if tok.has_space {
+            s.push(' ');
+        }
+        s.push_str(&tok.op);
+    }
+    s.push('\n');
+    s
+}
+
+// A value in an #if expression, which has type intmax_t or uintmax_t.
+#[derive(Clone, Copy)]
+struct Value {
+    val: i64,
+    unsigned: bool,
+}
+
+impl Value {
+    fn signed(val: i64) -> Self {
+        Value {
+            val,
+            unsigned: false,
+        }
+    }
+
+    fn with(self, val: i64) -> Self {
+        Value { val, ..self }
+    }
+}
+
+// Evaluator for #if expressions, with C's precedence and 64-bit arithmetic
+// that is unsigned if either operand is.
+struct Eval<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    // set while parsing an operand that is not evaluated, such as the right
+    // of a false &&, where errors like division by zero are not reported
+    skipping: bool,
+}
+
+impl<'a> Eval<'a> {
+    fn consume(&mut self, p: P) -> bool {
+        if self.pos < self.tokens.len() && self.tokens[self.pos].is(p) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn span(&self) -> Span {
+        match self.tokens.get(self.pos) {
+            Some(tok) => tok.span,
+            None => self.tokens[self.tokens.len() - 1].span,
+        }
+    }
+
+    // conditional = logor ("?" conditional ":" conditional)?
+    fn conditional(&mut self) -> Result<Value, CompileError> {
+        let cond = self.binary(0)?;
+        if !self.consume(P::Question) {
+            return Ok(cond);
+        }
+        let skipping = self.skipping;
+        self.skipping = skipping || cond.val == 0;
+        let then = self.conditional()?;
+        if !self.consume(P::Colon) {
+            return Err(error(
+                self.span(),
+                "expected ':' in #if expression".to_string(),
+            ));
+        }
+        self.skipping = skipping || cond.val != 0;
+        let els = self.conditional()?;
+        self.skipping = skipping;
+        let val = if cond.val != 0 { then.val } else { els.val };
+        Ok(Value {
+            val,
+            unsigned: then.unsigned || els.unsigned,
+        })
+    }
+
+    // binary operators from || (level 0) down to * / % (level 9)
+    fn binary(&mut self, level: usize) -> Result<Value, CompileError> {
+        static LEVELS: [&[P]; 10] = [
+            &[P::OrOr],
+            &[P::AndAnd],
+            &[P::Pipe],
+            &[P::Caret],
+            &[P::Amp],
+            &[P::EqEq, P::Ne],
+            &[P::Lt, P::Le, P::Gt, P::Ge],
+            &[P::Shl, P::Shr],
+            &[P::Plus, P::Minus],
+            &[P::Star, P::Slash, P::Percent],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut lhs = self.binary(level + 1)?;
+        'outer: loop {
+            for &op in LEVELS[level] {
+                let span = self.span();
+                if !self.consume(op) {
+                    continue;
+                }
+                let skipping = self.skipping;
+                if (op == P::OrOr && lhs.val != 0) || (op == P::AndAnd && lhs.val == 0) {
+                    self.skipping = true;
+                }
+                let rhs = self.binary(level + 1)?;
+                self.skipping = skipping;
+
+                // the usual arithmetic conversions, except for shifts, whose
+                // result has the type of the left operand
+                let unsigned = lhs.unsigned || rhs.unsigned;
+                let (l, r) = (lhs.val, rhs.val);
+                let (ul, ur) = (l as u64, r as u64);
+                let arith = Value { val: 0, unsigned };
+                let ord = if unsigned { ul.cmp(&ur) } else { l.cmp(&r) };
+                lhs = match op {
+                    P::OrOr => Value::signed((l != 0 || r != 0) as i64),
+                    P::AndAnd => Value::signed((l != 0 && r != 0) as i64),
+                    P::Pipe => arith.with(l | r),
+                    P::Caret => arith.with(l ^ r),
+                    P::Amp => arith.with(l & r),
+                    P::EqEq => Value::signed((l == r) as i64),
+                    P::Ne => Value::signed((l != r) as i64),
+                    P::Lt => Value::signed((ord == Ordering::Less) as i64),
+                    P::Le => Value::signed((ord != Ordering::Greater) as i64),
+                    P::Gt => Value::signed((ord == Ordering::Greater) as i64),
+                    P::Ge => Value::signed((ord != Ordering::Less) as i64),
+                    P::Shl => lhs.with(l.wrapping_shl(r as u32)),
+                    P::Shr if lhs.unsigned => lhs.with(ul.wrapping_shr(r as u32) as i64),
+                    P::Shr => lhs.with(l.wrapping_shr(r as u32)),
+                    P::Plus => arith.with(l.wrapping_add(r)),
+                    P::Minus => arith.with(l.wrapping_sub(r)),
+                    P::Star => arith.with(l.wrapping_mul(r)),
+                    _ if r == 0 && self.skipping => arith,
+                    _ if r == 0 => return Err(error(span, "division by zero in #if".to_string())),
+                    P::Slash if unsigned => arith.with((ul / ur) as i64),
+                    P::Slash => arith.with(l.wrapping_div(r)),
+                    _ if unsigned => arith.with((ul % ur) as i64),
+                    _ => arith.with(l.wrapping_rem(r)),
+                };
+                continue 'outer;
+            }
+            return Ok(lhs);
+        }
+    }
+
+    // unary = ("+" | "-" | "!" | "~") unary | "(" conditional ")" | num
+    fn unary(&mut self) -> Result<Value, CompileError> {
+        if self.consume(P::Plus) {
+            return self.unary();
+        }
+        if self.consume(P::Minus) {
+            let operand = self.unary()?;
+            return Ok(operand.with(operand.val.wrapping_neg()));
+        }
+        if self.consume(P::Not) {
+            return Ok(Value::signed((self.unary()?.val == 0) as i64));
+        }
+        if self.consume(P::Tilde) {
+            let operand = self.unary()?;
+            return Ok(operand.with(!operand.val));
+        }
+        if self.consume(P::LParen) {
+            let val = self.conditional()?;
+            if !self.consume(P::RParen) {
+                return Err(error(self.span(), "missing ')' in expression".to_string()));
+            }
+            return Ok(val);
+        }
+
+        match self.tokens.get(self.pos) {
+            Some(tok) if tok.kind == TokenKind::TkNum => {
+                self.pos += 1;
+                // every constant is intmax_t here, unless it has a u suffix
+                // or does not fit
+                let suffixed = !tok.op.starts_with('\'') && tok.op.contains(['u', 'U']);
+                Ok(Value {
+                    val: tok.val as i64,
+                    unsigned: suffixed || tok.val > i64::MAX as u64,
+                })
+            }
+            Some(tok) => Err(error(
+                tok.span,
+                format!(
+                    "token \"{}\" is not valid in preprocessor expressions",
+                    tok.op
+                ),
+            )),
+            None => Err(error(self.span(), "#if with no expression".to_string())),
+        }
+    }
+}
@@ -1,150 +1,744 @@
-use std::process;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
 
-fn strtol(s: &str) -> (&str, String) {
-    let n = s.find(|c: char| !c.is_digit(10)).unwrap_or(s.len());
-    let (op, r) = s.split_at(n);
+use crate::error::{CompileError, ErrorKind, Source};
+use crate::types::Type;
 
-    (op, r.to_string())
+fn is_alnum(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
 }
 
-fn strtos(s: &str) -> (String, String) {
-    let n = s.find(|c: char| !is_alnum(c)).unwrap_or(s.len());
-    let (op, r) = s.split_at(n);
+// The spelling of a token, interned so that each distinct spelling is
+// stored once however many tokens share it, and so that a token's spelling
+// is copied and compared as cheaply as an integer.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Symbol(u32);
 
-    (op.to_string(), r.to_string())
+// The interned strings live as long as the program, which is fine for a
+// compiler: there are only as many as the distinct spellings in its input.
+struct Interner {
+    ids: HashMap<&'static str, Symbol>,
+    strs: Vec<&'static str>,
 }
 
-fn strchr(s: &str, ch: char) -> bool {
-    let vec = s.chars().collect::<Vec<char>>();
-    vec.contains(&ch)
+thread_local! {
+    // Symbol 0, the default, is the empty string.
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner {
+        ids: HashMap::from([("", Symbol(0))]),
+        strs: vec![""],
+    });
 }
 
-fn is_alnum(c: char) -> bool {
-    c.is_ascii_alphabetic() || c.is_digit(10) || c == '_'
+impl Symbol {
+    pub fn intern(s: &str) -> Self {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(&sym) = interner.ids.get(s) {
+                return sym;
+            }
+            let s: &'static str = Box::leak(s.into());
+            let sym = Symbol(interner.strs.len() as u32);
+            interner.strs.push(s);
+            interner.ids.insert(s, sym);
+            sym
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        INTERNER.with(|interner| interner.borrow().strs[self.0 as usize])
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
 }
 
-#[derive(PartialEq)]
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+// Defines an enum of the tokens with a fixed spelling, along with the
+// conversions between a variant and its spelling.
+macro_rules! spelled {
+    ($name:ident { $($variant:ident = $s:literal,)* }) => {
+        #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+        pub enum $name {
+            $($variant,)*
+        }
+
+        impl $name {
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $($name::$variant => $s,)*
+                }
+            }
+
+            fn from_str(s: &str) -> Option<Self> {
+                match s {
+                    $($s => Some($name::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+// The keywords of C11.
+spelled!(Kw {
+    Auto = "auto",
+    Break = "break",
+    Case = "case",
+    Char = "char",
+    Const = "const",
+    Continue = "continue",
+    Default = "default",
+    Do = "do",
+    Double = "double",
+    Else = "else",
+    Enum = "enum",
+    Extern = "extern",
+    Float = "float",
+    For = "for",
+    Goto = "goto",
+    If = "if",
+    Inline = "inline",
+    Int = "int",
+    Long = "long",
+    Register = "register",
+    Restrict = "restrict",
+    Return = "return",
+    Short = "short",
+    Signed = "signed",
+    Sizeof = "sizeof",
+    Static = "static",
+    Struct = "struct",
+    Switch = "switch",
+    Typedef = "typedef",
+    Union = "union",
+    Unsigned = "unsigned",
+    Void = "void",
+    Volatile = "volatile",
+    While = "while",
+    Alignas = "_Alignas",
+    Alignof = "_Alignof",
+    Atomic = "_Atomic",
+    Bool = "_Bool",
+    Complex = "_Complex",
+    Generic = "_Generic",
+    Imaginary = "_Imaginary",
+    Noreturn = "_Noreturn",
+    StaticAssert = "_Static_assert",
+    ThreadLocal = "_Thread_local",
+});
+
+// The punctuators of C11, except for the digraphs.
+spelled!(P {
+    LBracket = "[",
+    RBracket = "]",
+    LParen = "(",
+    RParen = ")",
+    LBrace = "{",
+    RBrace = "}",
+    Dot = ".",
+    Arrow = "->",
+    Inc = "++",
+    Dec = "--",
+    Amp = "&",
+    Star = "*",
+    Plus = "+",
+    Minus = "-",
+    Tilde = "~",
+    Not = "!",
+    Slash = "/",
+    Percent = "%",
+    Shl = "<<",
+    Shr = ">>",
+    Lt = "<",
+    Gt = ">",
+    Le = "<=",
+    Ge = ">=",
+    EqEq = "==",
+    Ne = "!=",
+    Caret = "^",
+    Pipe = "|",
+    AndAnd = "&&",
+    OrOr = "||",
+    Question = "?",
+    Colon = ":",
+    Semi = ";",
+    Ellipsis = "...",
+    Assign = "=",
+    MulAssign = "*=",
+    DivAssign = "/=",
+    ModAssign = "%=",
+    AddAssign = "+=",
+    SubAssign = "-=",
+    ShlAssign = "<<=",
+    ShrAssign = ">>=",
+    AndAssign = "&=",
+    XorAssign = "^=",
+    OrAssign = "|=",
+    Comma = ",",
+    Hash = "#",
+    HashHash = "##",
+});
+
+#[derive(Default, PartialEq, Clone, Copy, Debug)]
 pub enum TokenKind {
-    TkReserved,
+    TkKeyword(Kw),
+    TkPunct(P),
     TkIdent,
+    #[default]
     TkNum,
+    TkStr,
     TkEof,
 }
 
-impl Default for TokenKind {
-    fn default() -> Self {
-        TokenKind::TkNum
-    }
+// Location of a token or node in the source, as a byte range in one of the
+// compiled files plus the 1-origin line and column of its first byte.
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct Span {
+    pub file: usize,
+    pub lo: usize,
+    pub hi: usize,
+    pub line: usize,
+    pub col: usize,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Debug)]
 pub struct Token {
     pub kind: TokenKind,
-    pub val: u32,
-    pub op: String,
+    pub val: u64,
+    pub fval: f64,         // value of a floating-point number
+    pub ty: Option<Type>,  // type of a number literal, int if not given
+    pub op: Symbol,        // spelling of the token as written in the source
+    pub contents: Vec<u8>, // bytes of a string literal, with the terminating NUL
+    pub span: Span,
+    pub bol: bool,            // first token on its line
+    pub has_space: bool,      // preceded by whitespace
+    pub hideset: Vec<Symbol>, // macros that must not be expanded again
 }
 
 impl Token {
-    fn new_token(kind: TokenKind, op: String) -> Self {
+    fn new_token(kind: TokenKind, op: &str, span: Span) -> Self {
         Self {
-            kind: kind,
-            op: op,
+            kind,
+            op: Symbol::intern(op),
+            span,
             ..Default::default()
         }
     }
 
-    fn new_token_num(val: u32) -> Self {
+    pub fn new_token_num(val: u64, span: Span) -> Self {
         Self {
-            val: val,
+            val,
+            op: Symbol::intern(&val.to_string()),
+            span,
             ..Default::default()
         }
     }
+
+    pub fn new_token_str(s: &str, span: Span) -> Self {
+        let mut op = String::from("\"");
+        for c in s.chars() {
+            if c == '"' || c == '\\' {
+                op.push('\\');
+            }
+            op.push(c);
+        }
+        op.push('"');
+
+        let mut contents = s.as_bytes().to_vec();
+        contents.push(0);
+
+        Self {
+            contents,
+            ..Token::new_token(TokenKind::TkStr, &op, span)
+        }
+    }
+
+    pub fn new_token_eof(span: Span) -> Self {
+        Token::new_token(TokenKind::TkEof, " ", span)
+    }
+
+    // identifiers and keywords are both identifiers to the preprocessor
+    pub fn is_ident(&self) -> bool {
+        matches!(self.kind, TokenKind::TkIdent | TokenKind::TkKeyword(_))
+    }
+
+    pub fn is(&self, p: P) -> bool {
+        self.kind == TokenKind::TkPunct(p)
+    }
+
+    pub fn is_kw(&self, kw: Kw) -> bool {
+        self.kind == TokenKind::TkKeyword(kw)
+    }
+}
+
+// Translation phase 2: deletes each backslash that ends a line, along with
+// the newline, so that the lines it joins are read as one. Returns the text
+// and, for each splice, its offset in the result and the number of bytes
+// deleted. The text is only copied if it has splices.
+fn splice_lines(text: &str) -> (Cow<'_, str>, Vec<(usize, usize)>) {
+    let mut out = String::new();
+    let mut splices = vec![];
+    let mut copied = 0;
+    for (i, _) in text.match_indices('\\') {
+        let len = if text[i + 1..].starts_with('\n') {
+            2
+        } else if text[i + 1..].starts_with("\r\n") {
+            3
+        } else {
+            continue;
+        };
+        out.push_str(&text[copied..i]);
+        splices.push((out.len(), len));
+        copied = i + len;
+    }
+
+    if splices.is_empty() {
+        return (Cow::Borrowed(text), splices);
+    }
+    out.push_str(&text[copied..]);
+    (Cow::Owned(out), splices)
+}
+
+// Walks the spliced source text by byte offset, tracking the line and column
+// of the current position in the original text. Tokens are made from slices
+// of the text, so the input is only copied if it has splices.
+struct Cursor<'a> {
+    text: &'a str,
+    pos: usize,
+    file: usize,
+    line: usize,
+    line_start: usize, // in the original text
+    splices: &'a [(usize, usize)],
+    spliced: usize, // number of splices passed so far
+    removed: usize, // bytes deleted by those splices
 }
 
-fn is_reserved(s: &str) -> bool {
-    let keywords = ["return", "if", "else", "while", "for", "sizeof"];
-    for keyword in &keywords {
-        if &s == keyword {
-            return true;
+impl<'a> Cursor<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn peek(&self, n: usize) -> Option<u8> {
+        self.text.as_bytes().get(self.pos + n).copied()
+    }
+
+    // Passes the splices at or before pos, each of which ended a line.
+    fn splices_to(&mut self, pos: usize) {
+        while let Some(&(at, len)) = self.splices.get(self.spliced) {
+            if at > pos {
+                break;
+            }
+            self.spliced += 1;
+            self.removed += len;
+            self.line += 1;
+            self.line_start = at + self.removed;
+        }
+    }
+
+    // Records that the byte at pos is a newline.
+    fn newline(&mut self, pos: usize) {
+        self.splices_to(pos);
+        self.line += 1;
+        self.line_start = pos + self.removed + 1;
+    }
+
+    fn span(&mut self, lo: usize, len: usize) -> Span {
+        self.splices_to(lo);
+        // a token may itself contain splices
+        let inner: usize = self.splices[self.spliced..]
+            .iter()
+            .take_while(|&&(at, _)| at < lo + len)
+            .map(|&(_, len)| len)
+            .sum();
+        Span {
+            file: self.file,
+            lo: lo + self.removed,
+            hi: lo + len + self.removed + inner,
+            line: self.line,
+            col: lo + self.removed - self.line_start + 1,
+        }
+    }
+}
+
+// Reads the character after a backslash in a string or character literal.
+// Returns the byte it stands for and the length of the escape sequence.
+fn read_escape(s: &str) -> Option<(u8, usize)> {
+    let c = s.chars().next()?;
+
+    // \ooo takes up to three octal digits and \xhh any number of hex digits
+    if ('0'..='7').contains(&c) {
+        let n = s
+            .find(|c| !('0'..='7').contains(&c))
+            .unwrap_or(s.len())
+            .min(3);
+        return Some((u32::from_str_radix(&s[..n], 8).ok()? as u8, n));
+    }
+    if c == 'x' {
+        let n = s[1..]
+            .find(|c: char| !c.is_ascii_hexdigit())
+            .unwrap_or(s.len() - 1);
+        let val = u64::from_str_radix(&s[1..n + 1], 16).ok()?;
+        return Some((val as u8, n + 1));
+    }
+
+    let b = match c {
+        'a' => 7,
+        'b' => 8,
+        't' => b'\t',
+        'n' => b'\n',
+        'v' => 11,
+        'f' => 12,
+        'r' => b'\r',
+        'e' => 27, // GNU extension
+        _ if c.is_ascii() => c as u8,
+        _ => return None,
+    };
+    Some((b, 1))
+}
+
+// Decodes the contents of a string or character literal, between its
+// quotes. On failure, returns the offset of the bad escape sequence.
+fn read_literal(s: &str) -> Result<Vec<u8>, usize> {
+    let mut bytes = vec![];
+    let mut i = 0;
+    while i < s.len() {
+        let c = s[i..].chars().next().unwrap();
+        if c == '\\' {
+            let (b, len) = read_escape(&s[i + 1..]).ok_or(i)?;
+            bytes.push(b);
+            i += len + 1;
+        } else {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            i += c.len_utf8();
         }
     }
+    Ok(bytes)
+}
 
-    let types = ["int"];
-    for ty in &types {
-        if &s == ty {
-            return true;
+// Reads a decimal floating-point number such as 1.5, .5e-3 or 2e10f, and
+// returns its value, whether it has type float, and its length. Returns None
+// for an integer.
+fn read_float(s: &str) -> Option<(f64, bool, usize)> {
+    let digits = |from: usize| {
+        s[from..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(s.len() - from)
+    };
+    let bytes = s.as_bytes();
+
+    let mut is_float = false;
+    let mut n = digits(0);
+    if bytes.get(n) == Some(&b'.') {
+        is_float = true;
+        n += 1 + digits(n + 1);
+    }
+    if matches!(bytes.get(n), Some(b'e' | b'E')) {
+        let mut exp = n + 1;
+        if matches!(bytes.get(exp), Some(b'+' | b'-')) {
+            exp += 1;
+        }
+        if digits(exp) > 0 {
+            is_float = true;
+            n = exp + digits(exp);
         }
     }
+    if !is_float {
+        return None;
+    }
+
+    let val = s[..n].parse().ok()?;
+    match bytes.get(n) {
+        Some(b'f' | b'F') => Some((val, true, n + 1)),
+        Some(b'l' | b'L') => Some((val, false, n + 1)),
+        _ => Some((val, false, n)),
+    }
+}
+
+// Reads an integer constant in decimal, octal (with a leading 0), hex (0x)
+// or binary (0b), with an optional u, l or ll suffix, and returns its value
+// and type. As in C11 6.4.4.1, the type is the first of int, long and, for a
+// constant that is not decimal, their unsigned versions in which the value
+// fits; u allows only the unsigned types and l only the long ones.
+fn read_int(s: &str) -> Result<(u64, Type), String> {
+    let (radix, start) = match s.get(..2) {
+        Some("0x" | "0X") => (16, 2),
+        Some("0b" | "0B") => (2, 2),
+        _ if s.starts_with('0') => (8, 1),
+        _ => (10, 0),
+    };
+    let n = s[start..]
+        .find(|c: char| {
+            if radix == 16 {
+                !c.is_ascii_hexdigit()
+            } else {
+                !c.is_ascii_digit()
+            }
+        })
+        .map_or(s.len(), |n| start + n);
+    let (digits, suffix) = (&s[start..n], &s[n..]);
+
+    // the x or b of a prefix with no digits is taken as a suffix of 0
+    if digits.is_empty() && radix != 8 {
+        return Err(format!(
+            "invalid suffix \"{}\" on integer constant",
+            &s[1..]
+        ));
+    }
+    let (is_unsigned, is_long) = match suffix {
+        "" => (false, false),
+        "u" | "U" => (true, false),
+        "l" | "L" | "ll" | "LL" => (false, true),
+        "ul" | "uL" | "Ul" | "UL" | "ull" | "uLL" | "Ull" | "ULL" | "lu" | "lU" | "Lu" | "LU"
+        | "llu" | "llU" | "LLu" | "LLU" => (true, true),
+        _ => return Err(format!("invalid suffix \"{}\" on integer constant", suffix)),
+    };
+    if let Some(c) = digits.chars().find(|c| c.to_digit(radix).is_none()) {
+        let name = if radix == 8 { "octal" } else { "binary" };
+        return Err(format!("invalid digit \"{}\" in {} constant", c, name));
+    }
+    let val = match digits {
+        "" => 0,
+        _ => u64::from_str_radix(digits, radix)
+            .map_err(|_| "integer constant is too large for its type".to_string())?,
+    };
+
+    let fits_int = !is_long && val <= i32::MAX as u64;
+    let fits_uint = !is_long && val <= u32::MAX as u64;
+    let fits_long = val <= i64::MAX as u64;
+    let ty = if is_unsigned {
+        if fits_uint {
+            Type::new_uint()
+        } else {
+            Type::new_ulong()
+        }
+    } else if fits_int {
+        Type::new_int()
+    } else if fits_uint && radix != 10 {
+        Type::new_uint()
+    } else if fits_long {
+        Type::new_long()
+    } else {
+        Type::new_ulong()
+    };
+
+    Ok((val, ty))
+}
 
-    false
+// Reads the longest punctuator at the start of s.
+fn read_punct(s: &str) -> Option<(P, usize)> {
+    (1..=3)
+        .rev()
+        .find_map(|len| Some((P::from_str(s.get(..len)?)?, len)))
 }
 
-pub fn tokenize(s: String) -> Vec<Token> {
+pub fn tokenize(src: &Source, file: usize) -> Result<Vec<Token>, CompileError> {
     let mut tokens: Vec<Token> = vec![];
+    let (text, splices) = splice_lines(&src.text);
+    let mut cur = Cursor {
+        text: &text,
+        pos: 0,
+        file,
+        line: 1,
+        line_start: 0,
+        splices: &splices,
+        spliced: 0,
+        removed: 0,
+    };
+    let mut bol = true;
+    let mut has_space = false;
 
-    let mut expr = s;
-    while let Some(c) = expr.chars().nth(0) {
-        if c.is_whitespace() {
-            expr = expr.split_off(1);
+    while let Some(c) = cur.peek(0) {
+        let pos = cur.pos;
+        let rest = cur.rest();
+
+        if c == b'\n' {
+            cur.newline(pos);
+            bol = true;
+        }
+
+        if matches!(c, b' ' | b'\t' | b'\n' | b'\r' | b'\x0b' | b'\x0c') {
+            has_space = true;
+            cur.pos += 1;
             continue;
         }
 
-        if c == '>' || c == '<' {
-            if expr.chars().nth(1).unwrap() == '=' {
-                let v = expr.split_off(2);
-                tokens.push(Token::new_token(TokenKind::TkReserved, expr));
-                expr = v;
-                continue;
+        // a comment is replaced by a space; a line comment ends before the
+        // newline
+        if rest.starts_with("//") {
+            has_space = true;
+            cur.pos += rest.find('\n').unwrap_or(rest.len());
+            continue;
+        }
+        if let Some(body) = rest.strip_prefix("/*") {
+            let len = match body.find("*/") {
+                Some(n) => n + 4,
+                None => {
+                    return Err(CompileError::new(
+                        ErrorKind::InvalidToken,
+                        cur.span(pos, 2),
+                        "unterminated comment".to_string(),
+                    ))
+                }
+            };
+            for (i, _) in rest[..len].match_indices('\n') {
+                cur.newline(pos + i);
             }
-            tokens.push(Token::new_token(TokenKind::TkReserved, c.to_string()));
-            expr = expr.split_off(1);
+            has_space = true;
+            cur.pos += len;
             continue;
         }
 
-        if c == '=' || c == '!' {
-            if expr.chars().nth(1).unwrap() == '=' {
-                let v = expr.split_off(2);
-                tokens.push(Token::new_token(TokenKind::TkReserved, expr));
-                expr = v;
-                continue;
+        let float = if c.is_ascii_digit()
+            || (c == b'.' && cur.peek(1).is_some_and(|c| c.is_ascii_digit()))
+        {
+            read_float(rest)
+        } else {
+            None
+        };
+        let (token, len) = if let Some((val, is_float, len)) = float {
+            let ty = if is_float {
+                Type::new_float()
             } else {
-                if expr.chars().nth(1).unwrap() == '!' {
-                    eprintln!("unable tokenize");
-                    process::exit(1);
-                } else {
-                    tokens.push(Token::new_token(TokenKind::TkReserved, c.to_string()));
-                    expr = expr.split_off(1);
-                    continue;
+                Type::new_double()
+            };
+            let token = Token {
+                fval: val,
+                ty: Some(ty),
+                ..Token::new_token(TokenKind::TkNum, &rest[..len], cur.span(pos, len))
+            };
+            (token, len)
+        } else if let Some((p, len)) = read_punct(rest) {
+            (
+                Token::new_token(TokenKind::TkPunct(p), &rest[..len], cur.span(pos, len)),
+                len,
+            )
+        } else if c == b'"' || c == b'\'' {
+            let mut len = 1;
+            let mut escaped = false;
+            let mut closed = false;
+            for &b in &rest.as_bytes()[1..] {
+                if b == b'\n' {
+                    break;
+                }
+                len += 1;
+                if !escaped && b == c {
+                    closed = true;
+                    break;
                 }
+                escaped = !escaped && b == b'\\';
+            }
+            let what = if c == b'"' { "string" } else { "character" };
+            if !closed {
+                return Err(CompileError::new(
+                    ErrorKind::InvalidToken,
+                    cur.span(pos, 1),
+                    format!("unterminated {} literal", what),
+                ));
             }
-        }
 
-        if strchr("+-*/(){}[],;&", c) {
-            tokens.push(Token::new_token(TokenKind::TkReserved, c.to_string()));
-            expr = expr.split_off(1);
-            continue;
-        }
+            let mut contents = match read_literal(&rest[1..len - 1]) {
+                Ok(contents) => contents,
+                Err(n) => {
+                    return Err(CompileError::new(
+                        ErrorKind::InvalidToken,
+                        cur.span(pos + 1 + n, 2),
+                        "invalid escape sequence".to_string(),
+                    ))
+                }
+            };
+            let span = cur.span(pos, len);
 
-        if c.is_alphabetic() || c == '_' {
-            let (s, r) = strtos(&expr);
-            if is_reserved(&s) {
-                tokens.push(Token::new_token(TokenKind::TkReserved, s));
+            let token = if c == b'"' {
+                contents.push(0);
+                Token {
+                    contents,
+                    ..Token::new_token(TokenKind::TkStr, &rest[..len], span)
+                }
             } else {
-                tokens.push(Token::new_token(TokenKind::TkIdent, s));
-            }
-            expr = r;
-            continue;
-        }
+                if contents.is_empty() {
+                    return Err(CompileError::new(
+                        ErrorKind::InvalidToken,
+                        span,
+                        "empty character constant".to_string(),
+                    ));
+                }
+                // a character constant is an int with the value of a char
+                Token {
+                    val: contents[0] as i8 as u64,
+                    ..Token::new_token(TokenKind::TkNum, &rest[..len], span)
+                }
+            };
+            (token, len)
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            let len = rest
+                .bytes()
+                .position(|c| !is_alnum(c))
+                .unwrap_or(rest.len());
+            let s = &rest[..len];
+            let kind = Kw::from_str(s).map_or(TokenKind::TkIdent, TokenKind::TkKeyword);
+            (Token::new_token(kind, s, cur.span(pos, len)), len)
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .bytes()
+                .position(|c| !is_alnum(c))
+                .unwrap_or(rest.len());
+            let span = cur.span(pos, len);
+            let (val, ty) = read_int(&rest[..len])
+                .map_err(|msg| CompileError::new(ErrorKind::InvalidToken, span, msg))?;
+            let token = Token {
+                val,
+                ty: Some(ty),
+                ..Token::new_token(TokenKind::TkNum, &rest[..len], span)
+            };
+            (token, len)
+        } else {
+            let len = rest.chars().next().unwrap().len_utf8();
+            return Err(CompileError::new(
+                ErrorKind::InvalidToken,
+                cur.span(pos, len),
+                "unable tokenize".to_string(),
+            ));
+        };
 
-        if c.is_digit(10) {
-            let (n, r) = strtol(&expr);
-            let token = Token::new_token_num(n.parse().unwrap());
-            tokens.push(token);
-            expr = r;
-            continue;
-        }
+        tokens.push(Token {
+            bol,
+            has_space,
+            ..token
+        });
+        cur.pos += len;
+        bol = false;
+        has_space = false;
     }
-    tokens.push(Token::new_token(TokenKind::TkEof, " ".to_string()));
 
-    tokens
+    let mut eof = Token::new_token_eof(cur.span(text.len(), 0));
+    eof.bol = true;
+    tokens.push(eof);
+
+    Ok(tokens)
 }
@@ -1,38 +1,113 @@
-#[derive(PartialEq, Clone)]
+#[derive(Default, PartialEq, Clone, Debug)]
 pub enum TypeKind {
+    #[default]
     TyNone,
+    TyVoid,
+    TyBool,
+    TyChar,
+    TyShort,
     TyInt,
+    TyLong,
+    TyFloat,
+    TyDouble,
     TyPtr,
     TyArr,
+    TyStruct,
+    TyUnion,
+    TyEnum,
+    TyFunc,
 }
 
-impl Default for TypeKind {
-    fn default() -> Self {
-        TypeKind::TyNone
-    }
+#[derive(Clone, PartialEq, Debug)]
+pub struct Member {
+    pub name: String,
+    pub ty: Type,
+    pub offset: usize,
 }
 
-#[derive(Default, Clone, PartialEq)]
+#[derive(Default, Clone, PartialEq, Debug)]
 pub struct Type {
     pub kind: TypeKind,
     pub ptr_to: Option<Box<Type>>,
     pub size: usize,
     pub size_array: usize,
+    pub is_unsigned: bool,
+    pub incomplete: bool, // array of unknown length as in int a[], or struct declared without members
+    pub members: Vec<Member>, // members of a struct or union
+    pub tag: Option<String>, // tag of a struct or union
+    pub return_ty: Option<Box<Type>>, // return type of a function
+    pub params: Vec<Type>, // parameter types of a function
+    pub is_variadic: bool, // function taking "..." or, with no params, declared as f()
 }
 
 impl Type {
     fn new_type(kind: TypeKind, size: usize) -> Self {
         Self {
-            kind: kind,
-            size: size,
+            kind,
+            size,
             ..Default::default()
         }
     }
 
+    fn new_unsigned(kind: TypeKind, size: usize) -> Self {
+        Self {
+            is_unsigned: true,
+            ..Type::new_type(kind, size)
+        }
+    }
+
+    pub fn new_void() -> Self {
+        Type::new_type(TypeKind::TyVoid, 1)
+    }
+
+    pub fn new_bool() -> Self {
+        Type::new_type(TypeKind::TyBool, 1)
+    }
+
+    pub fn new_char() -> Self {
+        Type::new_type(TypeKind::TyChar, 1)
+    }
+
+    pub fn new_short() -> Self {
+        Type::new_type(TypeKind::TyShort, 2)
+    }
+
     pub fn new_int() -> Self {
         Type::new_type(TypeKind::TyInt, 4)
     }
 
+    pub fn new_long() -> Self {
+        Type::new_type(TypeKind::TyLong, 8)
+    }
+
+    pub fn new_float() -> Self {
+        Type::new_type(TypeKind::TyFloat, 4)
+    }
+
+    pub fn new_double() -> Self {
+        Type::new_type(TypeKind::TyDouble, 8)
+    }
+
+    pub fn new_enum() -> Self {
+        Type::new_type(TypeKind::TyEnum, 4)
+    }
+
+    pub fn new_uchar() -> Self {
+        Type::new_unsigned(TypeKind::TyChar, 1)
+    }
+
+    pub fn new_ushort() -> Self {
+        Type::new_unsigned(TypeKind::TyShort, 2)
+    }
+
+    pub fn new_uint() -> Self {
+        Type::new_unsigned(TypeKind::TyInt, 4)
+    }
+
+    pub fn new_ulong() -> Self {
+        Type::new_unsigned(TypeKind::TyLong, 8)
+    }
+
     pub fn pointer_to(self) -> Self {
         Type {
             kind: TypeKind::TyPtr,
@@ -48,10 +123,182 @@ impl Type {
             size: self.size * n,
             ptr_to: Some(Box::new(self)),
             size_array: n,
+            ..Default::default()
+        }
+    }
+
+    // va_list, as laid out by the SysV ABI: an array of one struct that
+    // tracks the next argument in the register save area and on the stack.
+    pub fn new_va_list() -> Self {
+        let member = |name: &str, ty: Type| Member {
+            name: name.to_string(),
+            ty,
+            offset: 0,
+        };
+        let members = vec![
+            member("gp_offset", Type::new_uint()),
+            member("fp_offset", Type::new_uint()),
+            member("overflow_arg_area", Type::new_void().pointer_to()),
+            member("reg_save_area", Type::new_void().pointer_to()),
+        ];
+        Type::struct_of(Some("__va_list_tag".to_string()), members, false).array_of(1)
+    }
+
+    // Whether this is a va_list, or the pointer it decays to.
+    pub fn is_va_list(&self) -> bool {
+        self.is_pointer() && self.ptr_to.as_ref().unwrap().tag.as_deref() == Some("__va_list_tag")
+    }
+
+    pub fn incomplete_array_of(self) -> Self {
+        Self {
+            incomplete: true,
+            ..self.array_of(0)
         }
     }
 
+    pub fn func_type(return_ty: Type, params: Vec<Type>, is_variadic: bool) -> Self {
+        Self {
+            kind: TypeKind::TyFunc,
+            size: 1,
+            return_ty: Some(Box::new(return_ty)),
+            params,
+            is_variadic,
+            ..Default::default()
+        }
+    }
+
+    // A function declared as f() takes any arguments, as in K&R C.
+    pub fn is_unprototyped(&self) -> bool {
+        self.params.is_empty() && self.is_variadic
+    }
+
+    // Whether two declarations of a function agree on its type.
+    pub fn is_compatible_func(&self, other: &Type) -> bool {
+        if self.return_ty != other.return_ty {
+            return false;
+        }
+        if self.is_unprototyped() || other.is_unprototyped() {
+            return true;
+        }
+        self.params == other.params && self.is_variadic == other.is_variadic
+    }
+
+    // Lays out the members of a struct or union following the SysV ABI:
+    // each member of a struct is placed at the next offset aligned for its
+    // type, every member of a union at offset 0, and the size is rounded up
+    // to the alignment of the most aligned member.
+    pub fn struct_of(tag: Option<String>, mut members: Vec<Member>, is_union: bool) -> Self {
+        let mut size = 0;
+        for member in &mut members {
+            if is_union {
+                member.offset = 0;
+                size = size.max(member.ty.size);
+            } else {
+                member.offset = align_to(size, member.ty.align());
+                size = member.offset + member.ty.size;
+            }
+        }
+
+        let mut ty = Self {
+            kind: if is_union {
+                TypeKind::TyUnion
+            } else {
+                TypeKind::TyStruct
+            },
+            members,
+            tag,
+            ..Default::default()
+        };
+        ty.size = align_to(size, ty.align());
+        ty
+    }
+
+    // A struct or union type whose members are given later, if at all.
+    pub fn incomplete_struct(tag: String, is_union: bool) -> Self {
+        Self {
+            incomplete: true,
+            ..Type::struct_of(Some(tag), vec![], is_union)
+        }
+    }
+
+    pub fn align(&self) -> usize {
+        match self.kind {
+            TypeKind::TyArr => self.ptr_to.as_ref().unwrap().align(),
+            TypeKind::TyStruct | TypeKind::TyUnion => {
+                self.members.iter().map(|m| m.ty.align()).max().unwrap_or(1)
+            }
+            _ => self.size.max(1),
+        }
+    }
+
+    pub fn is_struct(&self) -> bool {
+        self.kind == TypeKind::TyStruct || self.kind == TypeKind::TyUnion
+    }
+
+    pub fn find_member(&self, name: &str) -> Option<&Member> {
+        self.members.iter().find(|m| m.name == name)
+    }
+
     pub fn is_integer(&self) -> bool {
-        self.kind == TypeKind::TyInt
+        matches!(
+            self.kind,
+            TypeKind::TyBool
+                | TypeKind::TyChar
+                | TypeKind::TyShort
+                | TypeKind::TyInt
+                | TypeKind::TyLong
+                | TypeKind::TyEnum
+        )
+    }
+
+    pub fn is_flonum(&self) -> bool {
+        self.kind == TypeKind::TyFloat || self.kind == TypeKind::TyDouble
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_flonum()
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        self.kind == TypeKind::TyPtr || self.kind == TypeKind::TyArr
     }
+
+    // The type both operands of an arithmetic operator are converted to, by
+    // the usual arithmetic conversions of C11 6.3.1.8. Types narrower than
+    // int are promoted to int first.
+    pub fn common_type(ty1: &Type, ty2: &Type) -> Type {
+        if ty1.kind == TypeKind::TyDouble || ty2.kind == TypeKind::TyDouble {
+            return Type::new_double();
+        }
+        if ty1.kind == TypeKind::TyFloat || ty2.kind == TypeKind::TyFloat {
+            return Type::new_float();
+        }
+
+        let promote = |ty: &Type| {
+            if ty.size < 4 {
+                Type::new_int()
+            } else {
+                Type {
+                    kind: ty.kind.clone(),
+                    size: ty.size,
+                    is_unsigned: ty.is_unsigned,
+                    ..Default::default()
+                }
+            }
+        };
+        let (ty1, ty2) = (promote(ty1), promote(ty2));
+
+        if ty1.size != ty2.size {
+            return if ty1.size > ty2.size { ty1 } else { ty2 };
+        }
+        if ty2.is_unsigned {
+            return ty2;
+        }
+        ty1
+    }
+}
+
+// Rounds n up to the nearest multiple of align.
+pub fn align_to(n: usize, align: usize) -> usize {
+    n.div_ceil(align) * align
 }
@@ -5,14 +5,18 @@ cat <<EOF | cc -x c -c -o tmp2.o -
 int testFunc1() { return 5; }
 int testFunc2(int x, int y) { return x+y; }
 int testFunc3(int a, int b, int c, int d, int e, int f) { return a+b+c+d+e+f;}
+int testFunc4(int a, int b, int c, int d, int e, int f, int g, int h) { return a-b+c-d+e-f+g*h;}
+double testFuncD(int a, double b, float c, long d) { return a * b + c - d; }
+double testFuncD10(double a, double b, double c, double d, double e, double f, double g, double h, double i, double j) { return a+b+c+d+e+f+g+h+i*j; }
+int isAligned() { return ((unsigned long)__builtin_frame_address(0) & 15) == 0; }
+int isAligned7(int a, int b, int c, int d, int e, int f, int g) { return g * (((unsigned long)__builtin_frame_address(0) & 15) == 0); }
 EOF
 
 assert() {
     expected="$1"
     input="$2"
 
-    ${mmcc2} "$input" > tmp.s
-    gcc -fPIC -o tmp tmp.s tmp2.o
+    ${mmcc2} -o tmp -e "$input" tmp2.o
     ./tmp
     actual="$?"
 
@@ -24,6 +28,34 @@ assert() {
     fi
 }
 
+assert_error() {
+    expected="$1"
+    input="$2"
+
+    actual=$(${mmcc2} -e "$input" 2>&1 >/dev/null | head -n 1)
+
+    if [ "$actual" = "$expected" ]; then
+        echo "$input => $actual"
+    else
+        echo "$input => '$expected' expected, but got '$actual'"
+        exit 1
+    fi
+}
+
+assert_error_count() {
+    expected="$1"
+    shift
+
+    actual=$(${mmcc2} "$@" 2>&1 >/dev/null | grep -c ': error: ')
+
+    if [ "$actual" = "$expected" ]; then
+        echo "$* => $actual errors"
+    else
+        echo "$* => $expected errors expected, but got $actual"
+        exit 1
+    fi
+}
+
 assert 0 'int main() { return 0; }'
 assert 42 'int main() { return 42; }'
 assert 6 'int main() { return 3+3; }'
@@ -72,6 +104,16 @@ assert 100 'int main() { int ret; ret=0; int i; for (i=0; i<10; i=i+1) { int j;
 assert 5 'int main() { return testFunc1(); }'
 assert 3 'int main() { return testFunc2(1, 2); }'
 assert 21 'int main() { return testFunc3(1, 2, 3, 4, 5, 6); }'
+assert 53 'int main() { return testFunc4(1, 2, 3, 4, 5, 6, 7, 8); }'
+assert 1 'int main() { return isAligned(); }'
+assert 3 'int main() { return 2 + isAligned(); }'
+assert 4 'int main() { return 1 + (2 + isAligned()); }'
+assert 7 'int main() { return isAligned7(1, 2, 3, 4, 5, 6, 7); }'
+assert 8 'int main() { return 1 + isAligned7(1, 2, 3, 4, 5, 6, 7); }'
+assert 10 'int main() { return 1 + (2 + isAligned7(1, 2, 3, 4, 5, 6, 7)); }'
+assert 2 'int main() { return testFunc2(isAligned(), isAligned()); }'
+assert 36 'int sum8(int a, int b, int c, int d, int e, int f, int g, int h) { return a+b+c+d+e+f+g+h; } int main() { return sum8(1, 2, 3, 4, 5, 6, 7, 8); }'
+assert 9 'int last(int a, int b, int c, int d, int e, int f, char g, long h, short i) { return g + h - i; } int main() { return last(1, 2, 3, 4, 5, 6, 300, 10, 45); }'
 assert 3 'int main() { return ret(1, 2); } int ret(int x, int y) { return x+y; }'
 assert 21 'int main() { return ret(1, 2, 3, 4, 5, 6); } int ret(int a, int b, int c, int d, int e, int f) { return a+b+c+d+e+f; }'
 assert 8 'int main() { return fib(6); } int fib(int n) { if (n <= 2) { return 1; } else { return fib(n-1) + fib(n-2); } }'
@@ -82,7 +124,7 @@ assert 3 'int main() { int x; int y; x=3; y=5; return *(&y-1); }'
 assert 4 'int main() { int x; return sizeof(x); }'
 assert 8 'int main() { int *y; return sizeof(y); }'
 assert 4 'int main() { return sizeof(1); }'
-assert 4 'int main() { return sizeof(sizeof(1)); }'
+assert 8 'int main() { return sizeof(sizeof(1)); }'
 assert 4 'int main() { int x; return sizeof(x+3); }'
 assert 8 'int main() { int *y; return sizeof(y+3); }'
 assert 4 'int main() { int *y; return sizeof(*y); }'
@@ -105,4 +147,520 @@ assert 4 'int main() { int x[2][3]; x[1][1]=4; return x[1][1]; }'
 assert 5 'int main() { int x[2][3]; int *y; y=x; y[5]=5; return x[1][2]; }'
 assert 6 'int main() { int x[2][3]; int *y; y=x; y[6]=6; return x[2][0]; }'
 
+assert 1 'int main() { char x; x=1; return x; }'
+assert 3 'int main() { char x[3]; x[0]=-1; x[1]=2; int y; y=4; return x[0]+y; }'
+assert 1 'int main() { char x; return sizeof(x); }'
+assert 10 'int main() { char x[10]; return sizeof(x); }'
+assert 255 'int main() { char x; x=-1; return x + 256; }'
+assert 3 'int main() { return sub_char(7, 3, 1); } int sub_char(char a, char b, char c) { return a-b-c; }'
+assert 97 'int main() { return "abc"[0]; }'
+assert 99 'int main() { return "abc"[2]; }'
+assert 0 'int main() { return "abc"[3]; }'
+assert 4 'int main() { return sizeof("abc"); }'
+assert 7 'int main() { return "\a"[0]; }'
+assert 10 'int main() { return "\n"[0]; }'
+assert 27 'int main() { return "\e"[0]; }'
+assert 106 'int main() { return "\j"[0]; }'
+assert 0 'int main() { return "\0"[0]; }'
+assert 16 'int main() { return "\20"[0]; }'
+assert 65 'int main() { return "\101"[0]; }'
+assert 104 'int main() { return "\x68"[0]; }'
+assert 6 'int main() { return sizeof("ab" "cde"); }'
+assert 100 'int main() { return "ab" "cd"[3]; }'
+assert 97 "int main() { return 'a'; }"
+assert 10 "int main() { return '\\n'; }"
+assert 4 "int main() { return sizeof('a'); }"
+assert 39 "int main() { char *s; s = \"'\\\"\"; return s[0]; }"
+assert 0 'int main() { printf("hello, %s %d\n", "world", 42); return 0; }'
+assert 0 'int main() { return 1 + printf("%d %d %d %d %d %d %d\n", 1, 2, 3, 4, 5, 6, 7) - 15; }'
+
+assert 0 'int x; int main() { return x; }'
+assert 3 'int x; int main() { x = 3; return x; }'
+assert 7 'int x; int y; int main() { x = 3; y = 4; return x + y; }'
+assert 5 'int x[4]; int main() { x[2] = 5; return x[2]; }'
+assert 32 'int x[4][2]; int main() { return sizeof(x); }'
+assert 3 'int x = 3; int main() { return x; }'
+assert 6 'int x[3] = {1, 2, 3}; int main() { return x[0] + x[1] + x[2]; }'
+assert 0 'int x[3] = {1}; int main() { return x[2]; }'
+assert 5 'int x[2][3] = {{1, 2}, {4, 5, 6}}; int main() { return x[1][1]; }'
+assert 6 'int x[2][3] = {1, 2, 3, 4, 5, 6}; int main() { return x[1][2]; }'
+assert 16 'int x[] = {1, 2, 3, 4,}; int main() { return sizeof(x); }'
+assert 6 'char s[] = "hello"; int main() { return sizeof(s); }'
+assert 108 'char s[] = "hello"; int main() { return s[3]; }'
+assert 119 'char *p = "world"; int main() { return p[0]; }'
+assert 3 'int x[4] = {1, 2, 3, 4}; int *p = &x[2]; int main() { return *p; }'
+assert 4 'int x[4] = {1, 2, 3, 4}; int *p = x + 3; int main() { return *p; }'
+assert 8 'int x = 8; int *p = &x; int **pp = &p; int main() { return **pp; }'
+assert 6 'int x = 2 * (1 + 2); int main() { return x; }'
+
+assert 3 'int main() { int x = 3; return x; }'
+assert 5 'int main() { int x = 3, y = x + 2; return y; }'
+assert 7 'int main() { int x = 7; int *p = &x; return *p; }'
+assert 6 'int main() { int a[3] = {1, 2, 3}; return a[0] + a[1] + a[2]; }'
+assert 0 'int main() { int a[3] = {1}; return a[1] + a[2]; }'
+assert 0 'int main() { int a[3] = {}; return a[0] + a[1] + a[2]; }'
+assert 20 'int main() { int a[] = {1, 2, 3, 4, 5}; return sizeof(a); }'
+assert 5 'int main() { int a[2][3] = {{1, 2, 3}, {4, 5}}; return a[1][1]; }'
+assert 0 'int main() { int a[2][3] = {{1, 2, 3}, {4, 5}}; return a[1][2]; }'
+assert 6 'int main() { int a[2][3] = {1, 2, 3, 4, 5, 6}; return a[1][2]; }'
+assert 24 'int main() { int a[][3] = {{1}, {2}}; return sizeof(a); }'
+assert 2 'int main() { int a[2] = {1, 2, 3}; return a[1]; }'
+assert 108 'int main() { char s[] = "hello"; return s[2]; }'
+assert 6 'int main() { char s[] = "hello"; return sizeof(s); }'
+assert 0 'int main() { char s[8] = "hi"; return s[5]; }'
+
+assert 2 'int main() { int x = 2; { int x = 3; } return x; }'
+assert 3 'int main() { int x = 2; { int x = 3; return x; } }'
+assert 5 'int main() { int x = 2; { x = 5; } return x; }'
+assert 4 'int x = 4; int main() { return x; }'
+assert 7 'int x = 4; int main() { int x = 7; return x; }'
+assert 4 'int x = 4; int main() { { int x = 7; } return x; }'
+assert 3 'int f(int a) { int b = a; return b; } int main() { int a = 1; int b = 2; return f(3); }'
+assert 1 'int x; int x = 1; int main() { return x; }'
+
+assert 1 'int main() { _Bool x; return sizeof(x); }'
+assert 2 'int main() { short x; return sizeof(x); }'
+assert 8 'int main() { long x; return sizeof(x); }'
+assert 8 'int main() { long long x; return sizeof(x); }'
+assert 4 'int main() { unsigned x; return sizeof(x); }'
+assert 8 'int main() { long unsigned int x; return sizeof(x); }'
+assert 2 'int main() { signed short int x; return sizeof(x); }'
+assert 1 'int main() { char c = 255; return c == -1; }'
+assert 255 'int main() { unsigned char c = 255; return c; }'
+assert 1 'int main() { short s = 65535; return s == -1; }'
+assert 1 'int main() { unsigned short s = 65535; return s == 65535; }'
+assert 1 'int main() { _Bool b = 3; return b; }'
+assert 0 'int main() { _Bool b = 0; return b; }'
+assert 1 'int main() { int x = 2147483647; x = x + 1; return x < 0; }'
+assert 1 'int main() { long x = 2147483647; x = x + 1; return x > 0; }'
+assert 1 'int main() { unsigned x = 0; x = x - 1; return x > 0; }'
+assert 0 'int main() { int x = -1; unsigned y = 1; return x < y; }'
+assert 1 'int main() { long x = -1; unsigned y = 1; return x < y; }'
+assert 1 'int main() { unsigned x = 0 - 1; return x / 2 == 2147483647; }'
+assert 1 'int main() { int x = -7; return x / 2 == -3; }'
+assert 3 'int main() { signed char c = -3; return -c; }'
+assert 254 'int f(short a, unsigned char b) { return a + b; } int main() { return f(-1, 255); }'
+assert 4 'int main() { int a[5]; int *p = a + 4; return p - a; }'
+assert 2 'int main() { int a[3] = {1, 2, 3}; int *p = a + 2; return p[-1]; }'
+assert 3 'int main() { int a[3] = {1, 2, 3}; return *(1 + a + 1); }'
+assert 44 'int main() { char c; int x = (c = 300); return x; }'
+assert 1 'unsigned char g = 257; int main() { return g; }'
+assert 1 'long h = 0 - 1; int main() { return h < 0; }'
+
+assert 3 'int main() { struct { int a; int b; } x; x.a = 1; x.b = 2; return x.a + x.b; }'
+assert 8 'int main() { struct { int a; int b; } x; return sizeof(x); }'
+assert 16 'int main() { struct { char a; long b; } x; return sizeof(x); }'
+assert 8 'int main() { struct { char a; int b; } x; return sizeof(x); }'
+assert 12 'int main() { struct { char a; int b; char c; } x; return sizeof(x); }'
+assert 4 'int main() { struct { char a; int b; } x; return _Alignof(x); }'
+assert 8 'int main() { struct { char a; long b; } x; return _Alignof(x); }'
+assert 8 'int main() { union { int a; char b[6]; } x; return sizeof(x); }'
+assert 3 'int main() { union { int a; char b[4]; } x; x.a = 515; return x.b[0]; }'
+assert 7 'struct s { int a; int b; }; int main() { struct s x; struct s *p = &x; p->b = 7; return x.b; }'
+assert 16 'struct s { int a; long b; }; int main() { return sizeof(struct s); }'
+assert 4 'int main() { return _Alignof(int); }'
+assert 8 'int main() { return sizeof(int *); }'
+assert 12 'int main() { return sizeof(int[3]); }'
+assert 6 'int main() { struct t { int a[3]; } x; x.a[2] = 6; return x.a[2]; }'
+assert 5 'int main() { struct { int a; int b; } x = {4, 5}, y; y = x; return y.b; }'
+assert 3 'int main() { struct { int a; int b; } x = {3}; return x.a + x.b; }'
+assert 5 'int main() { struct { int a; int b; } x[2] = {1, 2, 3, 4}; return x[1].a + x[0].b; }'
+assert 9 'struct { char a; int b; } g = {4, 5}; int main() { return g.a + g.b; }'
+assert 6 'struct node { int val; struct node *next; }; int main() { struct node a = {1}; struct node b = {2}; struct node c = {3}; a.next = &b; b.next = &c; return a.val + a.next->val + a.next->next->val; }'
+assert 2 'int main() { struct x { int x; } x; x.x = 2; return x.x; }'
+assert 4 'int main() { struct s { int a; }; { struct s { char a; char b; char c; char d; }; } struct s y; return sizeof(y); }'
+assert 8 'struct s; struct s { int a; int b; }; int main() { struct s x; return sizeof(x); }'
+assert 7 'struct s { int a; int b; }; int get(struct s *p) { return p->b; } int main() { struct s x = {3, 7}; return get(&x); }'
+
+assert 0 'int main() { enum { zero, one, two }; return zero; }'
+assert 2 'int main() { enum { zero, one, two }; return two; }'
+assert 6 'int main() { enum { five = 5, six, seven }; return six; }'
+assert 13 'int main() { enum { a = 3, b = a * 3, c }; return a + c; }'
+assert 4 'int main() { enum t { x, y } v; return sizeof(v); }'
+assert 4 'enum t { x, y }; int main() { enum t v = y; return sizeof(enum t) + v - 1; }'
+assert 3 'enum { N = 3 }; int a[N]; int main() { return sizeof(a) / sizeof(a[0]); }'
+assert 1 'int main() { typedef int t; t x = 1; return x; }'
+assert 8 'typedef long t; int main() { t x; return sizeof(x); }'
+assert 3 'typedef struct { int a; int b; } pair; int main() { pair p = {1, 2}; return p.a + p.b; }'
+assert 8 'typedef int *ip, arr[2]; int main() { arr a; ip p = a; return sizeof(a) + sizeof(p) - 8; }'
+assert 2 'typedef int t; int main() { int t = 2; return t; }'
+assert 4 'typedef int t; int main() { { typedef char t; } t x; return sizeof(x); }'
+assert 1 'typedef int t; typedef int t; int main() { t x = 1; return x; }'
+assert 4 'typedef int t; int main() { return sizeof(t); }'
+assert 3 'typedef struct node node; struct node { int val; node *next; }; int main() { node a = {1}; node b = {2}; a.next = &b; return a.val + a.next->val; }'
+assert 7 'int add(int a, int b); int main() { return add(3, 4); } int add(int a, int b) { return a + b; }'
+assert 5 'extern int x; int main() { return x; } int x = 5;'
+assert 6 'int main() { int f(int), x; x = 3; return f(x); } int f(int a) { return a * 2; }'
+assert 3 'int g = 3; int *addr() { return &g; } int main() { return *addr(); }'
+assert 255 'unsigned char f() { return 255; } int main() { return f(); }'
+assert 1 'char f() { return -1; } int main() { return f() == -1; }'
+assert 44 'int f(char c) { return c; } int main() { return f(300); }'
+assert 8 'long f() { return 8; } int main() { return sizeof(f()); }'
+assert 4 'int x; void set(int v) { x = v; return; } int main() { set(4); return x; }'
+assert 6 'int f(void); int main() { return f(); } int f(void) { return 6; }'
+assert 2 'int f(int, char *); int main() { return f(2, 0); } int f(int a, char *p) { return a; }'
+assert 3 'int sum(int *a) { return a[0] + a[1]; } int main() { int x[2] = {1, 2}; return sum(x); }'
+assert 5 'int f(int a[]) { return a[1]; } int main() { int x[2] = {4, 5}; return f(x); }'
+assert 45 'int sum(int n, ...) { va_list ap; va_start(ap, n); int s = 0; int i; for (i = 0; i < n; i = i + 1) s = s + va_arg(ap, int); va_end(ap); return s; } int main() { return sum(9, 1, 2, 3, 4, 5, 6, 7, 8, 9); }'
+assert 0 'int fmt(char *buf, char *fmt, ...) { va_list ap; va_start(ap, fmt); int n = vsprintf(buf, fmt, ap); va_end(ap); return n; } int main() { char buf[32]; fmt(buf, "%d-%s-%ld", 12, "ab", 3); return strcmp(buf, "12-ab-3"); }'
+assert 10 'int f(int a, int b, int c, int d, int e, int f, int g, ...) { va_list ap; va_start(ap, g); long x = va_arg(ap, long); char *p = va_arg(ap, char *); return g + x + *p; } int main() { return f(1, 2, 3, 4, 5, 6, 7, 2, "\1"); }'
+assert 5 'int last(int n, ...) { va_list ap; va_start(ap, n); int x; while (n) { x = va_arg(ap, int); n = n - 1; } return x; } int main() { return last(3, 1, 2, 5); }'
+assert 0 '#include <stdarg.h>
+int main() { va_list ap; return 0; }'
+
+assert 5 'int main() { double x = 2.5; return x * 2; }'
+assert 7 'int main() { float x = 3.5f; return x * 2; }'
+assert 3 'int main() { double x = .5e1; return x - 2; }'
+assert 1 'int main() { return 0.1 + 0.2 != 0.3; }'
+assert 1 'int main() { return 1.5 < 2; }'
+assert 0 'int main() { return 2.5 > 3; }'
+assert 1 'int main() { return 2.0 >= 2; }'
+assert 1 'int main() { float f = 0.1f; double d = 0.1; return f != d; }'
+assert 3 'int main() { double x = 0.5; if (x) return 3; return 4; }'
+assert 4 'int main() { double x = 0; if (x) return 3; return 4; }'
+assert 255 'int main() { unsigned char c = 255.9; return c; }'
+assert 3 'int main() { long x = -3; double d = x; return -d; }'
+assert 1 'int main() { unsigned long u = 0 - 1; double d = u; return d > 1e19; }'
+assert 10 'int main() { double d = 1e19; unsigned long u = d; return u / 1000000000000000000; }'
+assert 9 'int main() { float f = 1e19; unsigned long u = f; return u / 1000000000000000000; }'
+assert 1 'int main() { double d = 12345.9; unsigned long u = d; return u == 12345; }'
+assert 10 'unsigned long u = 1e19; int main() { return u / 1000000000000000000; }'
+assert 1 'unsigned w = 3e9; int main() { return w == 3000000000u; }'
+assert 1 'int main() { _Bool b = 0.5; return b; }'
+assert 12 'int main() { return sizeof(float) + sizeof(double); }'
+assert 5 'double g = 2.5; float h = 2.5f; int main() { return g + h; }'
+assert 4 'double a[2] = {1.5, 2.5}; int main() { return a[0] + a[1]; }'
+assert 9 'struct s { char c; double d; }; int main() { struct s x = {1, 8.5}; return x.c + x.d; }'
+assert 6 'double half(double x) { return x / 2; } int main() { return half(12); }'
+assert 7 'float addf(float a, float b) { return a + b; } int main() { return addf(3.25f, 3.75f); }'
+assert 7 'int mix(int a, double b, int c, float d) { return a + b + c + d; } int main() { return mix(1, 2.5, 3, 0.5f); }'
+assert 55 'double sum(double a, double b, double c, double d, double e, double f, double g, double h, double i, double j) { return a+b+c+d+e+f+g+h+i+j; } int main() { return sum(1, 2, 3, 4, 5, 6, 7, 8, 9, 10); }'
+assert 6 'double testFuncD(int a, double b, float c, long d); int main() { return testFuncD(2, 2.5, 2.5f, 1); }'
+assert 126 'double testFuncD10(double a, double b, double c, double d, double e, double f, double g, double h, double i, double j); int main() { return testFuncD10(1, 2, 3, 4, 5, 6, 7, 8, 9, 10); }'
+assert 55 'double vsum(int n, ...) { va_list ap; va_start(ap, n); double s = 0; int i; for (i = 0; i < n; i = i + 1) s = s + va_arg(ap, double); return s; } int main() { return vsum(10, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0); }'
+assert 0 'int main() { char buf[32]; sprintf(buf, "%.2f %d %.1f", 1.5, 3, 0.25f); return strcmp(buf, "1.50 3 0.2"); }'
+
+assert 2 'int main() { return 17 % 5; }'
+assert 254 'int main() { return -7 % 5 + 256; }'
+assert 3 'int main() { unsigned x = 4294967295; return x % 4; }'
+assert 2 'int main() { return 6 & 3; }'
+assert 7 'int main() { return 6 | 3; }'
+assert 5 'int main() { return 6 ^ 3; }'
+assert 1 'int main() { return ~-2; }'
+assert 8 'int main() { return 1 << 3; }'
+assert 4 'int main() { return 33 >> 3; }'
+assert 255 'int main() { return -1 >> 1 & 255; }'
+assert 1 'int main() { unsigned x = 0 - 1; return x >> 31; }'
+assert 1 'int main() { long x = 1; return x << 40 >> 40; }'
+assert 1 'int main() { return 1 + 2 == 3; }'
+assert 1 'int main() { return 1 && 2; }'
+assert 0 'int main() { return 1 && 0; }'
+assert 1 'int main() { return 0 || 2; }'
+assert 0 'int main() { return 0 || 0; }'
+assert 3 'int x; int set() { x = 3; return 1; } int main() { 0 && set(); 1 || set(); int a = x; 1 && set(); return a * 10 + x; }'
+assert 1 'int main() { return !0; }'
+assert 0 'int main() { return !5; }'
+assert 1 'int main() { return !0.0; }'
+assert 5 'int main() { return 1 ? 5 : 6; }'
+assert 6 'int main() { return 0 ? 5 : 6; }'
+assert 8 'int main() { return sizeof(1 ? 1 : (long)2); }'
+assert 3 'int main() { int a[2] = {3, 4}; int *p = 1 ? a : 0; return *p; }'
+assert 2 'int main() { return (1, 2); }'
+assert 5 'int main() { int i; int j; for (i = 0, j = 5; i < j; i++, j--) {} return i + 2; }'
+assert 7 'int main() { int x = 2; x += 5; return x; }'
+assert 3 'int main() { int x = 10; x -= 7; return x; }'
+assert 12 'int main() { int x = 3; x *= 4; return x; }'
+assert 2 'int main() { int x = 10; x /= 4; return x; }'
+assert 1 'int main() { int x = 10; x %= 3; return x; }'
+assert 8 'int main() { int x = 1; x <<= 3; return x; }'
+assert 2 'int main() { int x = 9; x >>= 2; return x; }'
+assert 6 'int main() { int x = 7; x &= 14; return x; }'
+assert 15 'int main() { int x = 7; x |= 8; return x; }'
+assert 9 'int main() { int x = 7; x ^= 14; return x; }'
+assert 4 'int main() { int a[3] = {1, 2, 4}; int *p = a; p += 2; return *p; }'
+assert 2 'int main() { int a[3] = {1, 2, 4}; int i = 0; a[i++] += 1; return a[0] + i - 1; }'
+assert 2 'int main() { int i = 1; return ++i; }'
+assert 1 'int main() { int i = 1; return i++; }'
+assert 2 'int main() { int i = 1; i++; return i; }'
+assert 0 'int main() { int i = 1; return --i; }'
+assert 1 'int main() { int i = 1; return i--; }'
+assert 128 'int main() { char c = 127; return (c++ == 127) + c + 255; }'
+assert 3 'int main() { int a[3] = {1, 2, 3}; int *p = a; p++; ++p; return *p; }'
+assert 1 'int main() { int a[3] = {1, 2, 3}; int *p = a + 2; p--; --p; return *p; }'
+assert 2 'int main() { double d = 1.5; d++; d += 0.5; return d - 1; }'
+assert 1 'int main() { _Bool b = 1; return b++; }'
+assert 0 'int main() { _Bool b = 0; return b--; }'
+assert 1 'int main() { float f = 16777216; float g = f++; return g == 16777216; }'
+assert 1 'int main() { char c = 127; int x = c++; return x == 127 && c == -128; }'
+assert 1 'int main() { return (char)257; }'
+assert 255 'int main() { return (unsigned char)-1; }'
+assert 3 'int main() { return (int)3.9; }'
+assert 2 'int main() { long x = 2; return *(int *)&x; }'
+assert 0 'int main() { (void)1; return 0; }'
+assert 4 'int a[(1 ? 2 : 3) << 1]; int main() { return sizeof(a) / sizeof(int); }'
+assert 3 'int main() { enum { a = 7 % 4, b = a & 1 ? 3 : 0 }; return b; }'
+
+assert 10 'int main() { int i = 0; do { i++; } while (i < 10); return i; }'
+assert 1 'int main() { int i = 0; do i++; while (0); return i; }'
+assert 5 'int main() { int i = 0; while (1) { if (i == 5) break; i++; } return i; }'
+assert 20 'int main() { int i; int s = 0; for (i = 0; i < 10; i++) { if (i % 2) continue; s += i; } return s; }'
+assert 3 'int main() { int i = 0; int n = 0; do { i++; if (i == 3) continue; n += i; } while (i < 3); return n; }'
+assert 3 'int main() { int i; int n = 0; for (i = 0; i < 3; i++) { int j; for (j = 0; j < 10; j++) { if (j == 1) break; n++; } } return n; }'
+assert 5 'int main() { int x = 2; switch (x) { case 1: return 4; case 2: return 5; } return 6; }'
+assert 6 'int main() { int x = 3; switch (x) { case 1: return 4; case 2: return 5; } return 6; }'
+assert 7 'int main() { int x = 3; switch (x) { case 1: return 4; default: return 7; case 2: return 5; } }'
+assert 9 'int main() { int x = 1; int n = 0; switch (x) { case 1: n += 4; case 2: n += 5; break; case 3: n += 6; } return n; }'
+assert 13 'int main() { int s = 0; int i; for (i = 0; i < 6; i++) switch (i) { case 0: s += 1; break; case 1: s += 2; break; case 2: case 3: s += 3; break; case 5: s += 4; break; default: continue; } return s; }'
+assert 40 'int main() { int x = -2; switch (x) { case -3: return 30; case -2: return 40; case -1: return 50; case 0: return 60; } return 0; }'
+assert 60 'int main() { long x = 3; switch (x) { case 3: return 60; case 2: return 1; } return 0; }'
+assert 1 'int main() { unsigned x = -1; switch (x) { case -1: return 1; } return 0; }'
+assert 8 'int f(int x) { switch (x) { case 10: return 1; case 11: return 2; case 13: return 3; case 14: return 4; } return 8; } int main() { return f(12) + f(9) * 0; }'
+assert 3 'int f(int x) { switch (x) { case 10: return 1; case 11: return 2; case 13: return 3; case 14: return 4; } return 8; } int main() { return f(13); }'
+assert 2 'int main() { int i = 0; int n = 0; switch (i) { case 0: while (n < 5) { n++; if (n == 2) break; } } return n; }'
+assert 3 'int main() { int i = 0; goto end; i = 2; end: return i + 3; }'
+assert 10 'int main() { int i = 0; loop: i++; if (i < 10) goto loop; return i; }'
+assert 2 'int f() { goto a; a: return 1; } int main() { goto a; a: return f() + 1; }'
+assert 0 'int main() { ; ; return 0; }'
+
+assert 45 'int main() { int s = 0; for (int i = 0; i < 10; i++) s += i; return s; }'
+assert 3 'int main() { int i = 3; for (int i = 0; i < 10; i++) {} return i; }'
+assert 12 'int main() { int s = 0; for (int i = 0, j = 2; i < 3; i++) s += i * j; return s * 2; }'
+assert 2 'int main() { for (int i = 0; i < 2; i++) { int i = 5; } return 2; }'
+assert 6 'int main() { int a = 1; a = a + 1; int b = a * 3; return b; }'
+assert 3 'int main() { const int x = 3; return x; }'
+assert 4 'int main() { const char *p = "abcd"; char *const q = "x"; return sizeof(int const) + p[0] - 97; }'
+assert 1 'int main() { volatile int x = 1; return x; }'
+assert 8 'int main() { int * const volatile p = 0; return sizeof(p); }'
+assert 2 'typedef int T; int main() { T x = 1; T y = 1; return x + y; }'
+assert 3 'int main() { typedef long L; L x = 3; return x; }'
+assert 6 'int f() { static int n; n += 2; return n; } int main() { f(); f(); return f(); }'
+assert 12 'int f() { static int n = 10; return ++n; } int main() { f(); return f(); }'
+assert 5 'int main() { static int a[3] = {1, 2, 2}; return a[0] + a[1] + a[2]; }'
+assert 5 'static inline int f(register int a) { return a; } _Noreturn void g(); int main() { register int x = 2; auto int y = 3; return f(x + y); }'
+assert 7 'int f() { static int x = 3; return x; } int g() { static int x = 4; return x; } int main() { return f() + g(); }'
+assert 9 'static int x = 9; static int get() { return x; } int main() { return get(); }'
+assert 1 'int main() { switch (1) { case 1: {} int y = 1; return y; } return 0; }'
+
+assert 16 'int main() { return 0x10; }'
+assert 255 'int main() { return 0XfF; }'
+assert 8 'int main() { return 010; }'
+assert 0 'int main() { return 0; }'
+assert 5 'int main() { return 0b101; }'
+assert 4 'int main() { return sizeof(1); }'
+assert 8 'int main() { return sizeof(1L); }'
+assert 8 'int main() { return sizeof(1ll); }'
+assert 4 'int main() { return sizeof(1u); }'
+assert 8 'int main() { return sizeof(1UL) + sizeof(1LLU) - 8; }'
+assert 8 'int main() { return sizeof(2147483648); }'
+assert 4 'int main() { return sizeof(0x80000000); }'
+assert 8 'int main() { return sizeof(0x100000000); }'
+assert 1 'int main() { return 0xffffffff > 0; }'
+assert 1 'int main() { return -1 < 0x7fffffff; }'
+assert 0 'int main() { return -1 < 0xffffffff; }'
+assert 0 'int main() { return -1 < 1u; }'
+assert 1 'int main() { return -1 < 1L; }'
+assert 1 'int main() { long x = 10000000000; return x / 10000000000; }'
+assert 2 'int main() { long x = 0x123456789a; return (x >> 32) - 16; }'
+assert 255 'int main() { unsigned long x = 0xffffffffffffffff; return x >> 56; }'
+assert 1 'int main() { return 18446744073709551615 == -1; }'
+assert 3 'long x = 30000000000; int main() { return x / 10000000000; }'
+assert 1 'long x = (-9223372036854775807L - 1) / -1; int main() { return x == -9223372036854775807L - 1; }'
+assert 0 'long x = (-9223372036854775807L - 1) % -1; int main() { return x; }'
+assert 1 'unsigned long y = 18446744073709551615UL / 2; int main() { return y == 9223372036854775807; }'
+assert 8 'int main() { int a[(-1UL > 0) + 1]; return sizeof(a); }'
+assert 1 'long x = -1u; int main() { return x == 4294967295; }'
+assert 0 'long x = 4294967296; int f() { return x; } int main() { if ((int)x) return 1; return 0; }'
+assert 1 'long x = 4294967296; int f() { return x; } int main() { return !(int)x; }'
+assert 0 'long x = 4294967296; int f() { return x; } int main() { int y; if (y = x) return 1; return 0; }'
+assert 0 'long x = 4294967296; int f() { return x; } int main() { if (f()) return 1; return 0; }'
+assert 0 'long x = 4294967296; int f() { return x; } int main() { return (int)x ? 1 : 0; }'
+assert 0 'long x = 4294967296; int f() { return x; } int main() { return (int)x && 1; }'
+assert 0 'long x = 4294967296; int f() { return x; } int main() { return (int)x || 0; }'
+assert 0 'long x = 4294967296; int f() { return x; } int main() { return (_Bool)(int)x; }'
+assert 2 'long x = 4294967296; int f() { return x; } int main() { unsigned u; u = x + 2; return u; }'
+assert 9 'int x = (unsigned)-7 % 10; int main() { return x; }'
+assert 60 'int main() { long x = 10000000000; switch (x) { case 10000000000: return 60; case 2: return 1; } return 0; }'
+assert 1 '#if 0x10 == 16 && 1UL
+int main() { return 1; }
+#endif'
+
+assert 1 'int main() { return 1; } // comment'
+assert 2 'int main() { /* a */ return /* b
+*/ 2; }'
+assert 3 '// comment
+int main() { return 3; // comment
+}'
+assert 4 '// comment \
+int main() { return 5; }
+int main() { return 4; }'
+assert 3 '#define ADD(a, b) \
+  ((a) + (b))
+int main() { return ADD(1, 2); }'
+assert 6 'int main() { return 8 /* / */ - 2; }'
+assert 3 'int ma\
+in() { return 3; }'
+assert 99 'int main() { char *s = "ab\
+c"; return s[2]; }'
+assert 8 'int main() { int x = 1; x <\
+<= 3; return x; }'
+assert 4 $'int ma\\\r\nin() { return 4; }'
+assert 5 'int main() { char *s = "//"; char *t = "/*"; return s[0] + t[1] - 84; }'
+
+assert 3 '#define N 3
+int main() { return N; }'
+assert 8 '#define TWICE(x) ((x) + (x))
+int main() { return TWICE(2) * 2; }'
+assert 6 '#define CAT(a, b) a ## b
+int main() { int CAT(fo, o); foo = 6; return foo; }'
+assert 9 '#define SUM(...) sum3(__VA_ARGS__)
+int sum3(int a, int b, int c) { return a + b + c; }
+int main() { return SUM(2, 3, 4); }'
+assert 4 '#define A 1
+#if A + 1 == 2 && !defined(B)
+int main() { return 4; }
+#else
+int main() { return 5; }
+#endif'
+assert 5 '#ifdef A
+int main() { return 4; }
+#elif defined A || 1
+int main() { return 5; }
+#endif'
+assert 6 '#if defined(X) && 10 / X > 2
+int main() { return 7; }
+#elif 1 || 1 / 0
+int main() { return 0 ? 1 / 0 : 6; }
+#endif'
+assert 2 '#if 0xffffffff > -1 && 0xffffffffffffffff > 0
+int main() { return 2; }
+#endif'
+assert 3 '#if -1 > 0u && -1 / 2u > 0 && (0 ? 1u : -1) > 0 && -1 < 0 && (-1u >> 63) == 1
+int main() { return 3; }
+#endif'
+assert 4 '#define A
+#undef A
+#ifndef A
+int main() { return __LINE__; }
+#endif'
+
+assert_error '<input>:1:23: error: expected ; but got }' 'int main() { return 0 }'
+assert_error "<input>:1:21: error: 'x' undeclared" 'int main() { return x; }'
+assert_error "<input>:2:3: error: 'x' undeclared" $'int main() { return \\\n  x; }'
+assert_error '<input>:1:14: error: lvalue required as left operand of assignment' 'int main() { 1 = 2; }'
+assert_error "<input>:1:22: error: lvalue required as unary '&' operand" 'int main() { return &1; }'
+assert_error "<input>:1:21: error: invalid type argument of unary '*'" 'int main() { return *1; }'
+assert_error "<input>:1:29: error: invalid type argument of unary '*'" 'int main() { int x; return x[0]; }'
+assert_error "<input>:1:29: error: invalid type argument of unary '*'" 'int main() { int x; return x->a; }'
+assert_error "<input>:1:26: error: assignment to expression with array type" 'int main() { int a[2]; a = 1; return 0; }'
+assert_error "<input>:1:47: error: incompatible types when assigning" 'struct S {int a;}; int main() { struct S s; s = 1; return 0; }'
+assert_error "<input>:1:54: error: invalid operands to binary +" 'struct S {int a;}; int main() { struct S s; return s + 1; }'
+assert_error "<input>:1:54: error: invalid operands to binary -" 'struct S {int a;}; int main() { struct S s; return 1 - s; }'
+assert_error "<input>:1:33: error: invalid operands to binary +" 'int main() { int *p; return *(p + 1.5); }'
+assert_error "<input>:1:37: error: invalid operands to binary +" 'int main() { int *p, *q; return *(p + q); }'
+assert_error "<input>:1:31: error: invalid operands to binary -" 'int main() { int *p; return 1 - p; }'
+assert_error "<input>:1:49: error: used struct type value where scalar is required" 'struct S {int a;}; int main() { struct S s; if (s) return 1; return 0; }'
+assert_error "<input>:1:53: error: used struct type value where scalar is required" 'struct S {int a;}; int main() { struct S s; return !s; }'
+assert_error "<input>:1:52: error: wrong type argument to unary minus" 'struct S {int a;}; int main() { struct S s; return -s; }'
+assert_error "<input>:1:21: error: wrong type argument to unary minus" 'int main() { return -"abc"; }'
+assert_error "<input>:1:52: error: wrong type argument to unary plus" 'struct S {int a;}; int main() { struct S s; return +s; }'
+assert_error "<input>:1:54: error: invalid operands to binary <" 'struct S {int a;}; int main() { struct S s; return s < 2; }'
+assert_error "<input>:1:57: error: invalid operands to binary ==" 'struct S {int a;}; int main() { struct S s, t; return s == t; }'
+assert_error "<input>:1:78: error: incompatible types when assigning" 'struct A {int x;}; struct B {int x;}; int main() { struct A s; struct B t; s = t; return 0; }'
+assert_error "<input>:1:49: error: incompatible types when returning" 'struct A {int x;}; int f() { struct A s; return s; }'
+assert_error "<input>:1:53: error: incompatible types when initializing" 'struct A {int x;}; int main() { struct A s; int x = s; return 0; }'
+assert_error "<input>:1:77: error: incompatible types when initializing" 'struct A {int x;}; struct B {int x;}; int main() { struct B t; struct A s = t; return 0; }'
+assert_error "<input>:1:29: error: returning a struct by value is not supported; return a pointer to it" 'struct P {int x;}; struct P mk(int a) { struct P p; return p; } int main() { return 0; }'
+assert_error "<input>:1:42: error: returning a struct by value is not supported; return a pointer to it" 'struct P {int x;}; int main() { struct P mk(int); return 0; }'
+assert_error '<input>:1:23: error: unable tokenize' 'int main() { return 1 @ 2; }'
+assert_error "<input>:1:16: error: initializer element is not constant" 'int x; int y = x; int main() { return 0; }'
+assert_error "<input>:1:18: error: array size missing in 'a'" 'int main() { int a[]; return 0; }'
+assert_error "<input>:1:25: error: redeclaration of 'a'" 'int main() { int a; int a; return 0; }'
+assert_error "<input>:1:27: error: redeclaration of 'a'" 'int f(int a) { int b; int a; return 0; }'
+assert_error "<input>:1:32: error: 'y' undeclared" 'int main() { { int y; } return y; }'
+assert_error "<input>:1:16: error: redefinition of 'x'" 'int x = 1; int x = 2; int main() { return 0; }'
+assert_error '<input>:1:14: error: invalid combination of type specifiers' 'int main() { char int x; return 0; }'
+assert_error "<input>:1:42: error: no member named 'c'" 'int main() { struct { int a; } x; x.a = x.c; }'
+assert_error "<input>:1:29: error: request for member 'a' in something not a structure or union" 'int main() { int x; return x.a; }'
+assert_error "<input>:1:33: error: storage size of 'x' isn't known" 'int main() { struct s; struct s x; return 0; }'
+assert_error "<input>:1:34: error: redeclaration of 'a'" 'int main() { int a; enum { b, c, a }; return 0; }'
+assert_error '<input>:1:7: error: size of array is negative' 'int a[0 - 1]; int main() { return 0; }'
+assert_error '<input>:1:8: error: size of array is negative' 'long a[(-9223372036854775807L - 1) / -1 + 2]; int main() { return 0; }'
+assert_error "<input>:1:42: error: too few arguments to function 'f'" 'int f(int a, int b); int main() { return f(1); }'
+assert_error "<input>:1:40: error: too many arguments to function 'f'" 'int f(int a); int main() { return f(1, 2); }'
+assert_error "<input>:1:38: error: too many arguments to function 'f'" 'int main() { int f(int); return f(1, 2); }'
+assert_error "<input>:1:20: error: conflicting types for 'f'" 'int f(int a); char f(int a) { return a; }'
+assert_error "<input>:1:20: error: conflicting types for 'x'" 'extern int x; long x; int main() { return 0; }'
+assert_error "<input>:1:45: error: incompatible type for argument 1 of 'f'" 'int f(int *p); int main() { int a; return f(a); }'
+assert_error "<input>:1:28: error: called object 'x' is not a function" 'int main() { int x; return x(); }'
+assert_error "<input>:1:27: error: redefinition of 'f'" 'int f() { return 0; } int f() { return 1; }'
+assert_error "<input>:1:28: error: 'va_start' used in function with fixed arguments" 'int f(int n) { va_list ap; va_start(ap, n); return 0; }'
+assert_error "<input>:1:42: error: first argument to 'va_arg' is not of type 'va_list'" 'int f(int n, ...) { int x; return va_arg(x, int); }'
+assert_error "<input>:1:51: error: 'float' is promoted to 'double' when passed through '...'" 'int f(int n, ...) { va_list ap; return va_arg(ap, float); }'
+assert_error "<input>:1:33: error: invalid operands to binary %" 'int main() { double d; return d % 2; }'
+assert_error "<input>:1:31: error: invalid operands to binary <<" 'int main() { int *p; return p << 1; }'
+assert_error "<input>:1:21: error: wrong type argument to bit-complement" 'int main() { return ~1.5; }'
+assert_error "<input>:1:14: error: lvalue required as left operand of assignment" 'int main() { 1++; return 0; }'
+assert_error "<input>:1:14: error: break statement not within loop or switch" 'int main() { break; }'
+assert_error "<input>:1:27: error: continue statement not within a loop" 'int main() { switch (1) { continue; } }'
+assert_error "<input>:1:14: error: case label not within a switch statement" 'int main() { case 1: return 0; }'
+assert_error "<input>:1:14: error: 'default' label not within a switch statement" 'int main() { default: return 0; }'
+assert_error "<input>:1:40: error: duplicate case value" 'int main() { switch (1) { case 1: case 1: return 0; } }'
+assert_error "<input>:1:46: error: multiple default labels in one switch" 'int main() { switch (1) { default: return 0; default: return 1; } }'
+assert_error "<input>:1:22: error: switch quantity not an integer" 'int main() { switch (1.5) { } return 0; }'
+assert_error "<input>:1:39: error: case label does not reduce to an integer constant" 'int main() { int x; switch (1) { case x: return 0; } }'
+assert_error "<input>:1:17: error: duplicate label 'a'" 'int main() { a: a: return 0; }'
+assert_error "<input>:1:19: error: label 'b' used but not defined" 'int main() { goto b; a: return 0; }'
+assert_error "<input>:1:52: error: 'i' undeclared" 'int main() { for (int i = 0; i < 1; i++) {} return i; }'
+assert_error "<input>:1:36: error: initializer element is not constant" 'int main() { int a; static int b = a; return b; }'
+assert_error '<input>:1:21: error: invalid suffix "abc" on integer constant' 'int main() { return 12abc; }'
+assert_error '<input>:1:21: error: invalid suffix "x" on integer constant' 'int main() { return 0x; }'
+assert_error '<input>:1:21: error: invalid digit "9" in octal constant' 'int main() { return 09; }'
+assert_error '<input>:1:21: error: invalid digit "2" in binary constant' 'int main() { return 0b12; }'
+assert_error '<input>:1:21: error: invalid suffix "lul" on integer constant' 'int main() { return 1lul; }'
+assert_error '<input>:1:21: error: integer constant is too large for its type' 'int main() { return 18446744073709551616; }'
+assert_error '<input>:1:26: error: expected type name but got <' 'int main() { return 1; } <'
+assert_error '<input>:1:26: error: expected type name but got !' 'int main() { return 1; } !'
+assert_error '<input>:1:26: error: unterminated comment' 'int main() { return 1; } /* abc'
+assert_error "<input>:3:24: error: 'x' undeclared" '/*
+
+*/ int main() { return x; }'
+assert_error '<input>:1:18: error: expected identifier but got while' 'int main() { int while = 1; return 0; }'
+assert_error '<input>:1:18: error: expected identifier but got _Static_assert' 'int main() { int _Static_assert; return 0; }'
+assert_error_count 4 -e 'int main() { int a; a = ; b = 1; if (a) { c; } return a }'
+assert_error_count 3 -e 'int f( { return 1; } int g() { return q + r; }'
+assert_error_count 2 -fmax-errors=2 -e 'int main() { x; y; z; }'
+assert_error_count 1 -e 'int x = ; int main() { return x; }'
+
+# source files, stdin and the -S, -c and link modes
+echo 'int main() { return 42; }' > tmp.c
+rm -f tmp.s tmp.o
+${mmcc2} -S tmp.c && gcc -o tmp tmp.s && ./tmp
+[ "$?" = 42 ] || { echo "-S tmp.c => 42 expected"; exit 1; }
+${mmcc2} -c tmp.c && gcc -o tmp tmp.o && ./tmp
+[ "$?" = 42 ] || { echo "-c tmp.c => 42 expected"; exit 1; }
+${mmcc2} -o tmp tmp.c && ./tmp
+[ "$?" = 42 ] || { echo "tmp.c => 42 expected"; exit 1; }
+${mmcc2} -c tmp.c -o tmp.o -lm && gcc -o tmp tmp.o && ./tmp
+[ "$?" = 42 ] || { echo "-c tmp.c -o tmp.o -lm => 42 expected"; exit 1; }
+rm -f tmp.map
+${mmcc2} -o tmp tmp.c -Wl,-Map,tmp.map && [ -f tmp.map ] || { echo "-Wl, not passed to the linker"; exit 1; }
+echo 'int main() { return 7; }' | ${mmcc2} -o tmp - && ./tmp
+[ "$?" = 7 ] || { echo "stdin => 7 expected"; exit 1; }
+mkdir -p tmp.inc
+echo '#define FORTY_TWO 42' > tmp.inc/tmp.h
+${mmcc2} -o tmp -Itmp.inc -e '#include <tmp.h>
+int main() { return FORTY_TWO; }' && ./tmp
+[ "$?" = 42 ] || { echo "-I => 42 expected"; exit 1; }
+echo '#include "self.h"' > tmp.inc/self.h
+${mmcc2} -E -Itmp.inc -e '#include "self.h"' 2>&1 | grep -q 'nested depth 201 exceeds maximum of 200' \
+  || { echo "recursive #include => depth error expected"; exit 1; }
+rm -rf tmp.inc
+[ "$(${mmcc2} -E -DX=1 -e 'X + __FILE__')" = '1 + "<input>"' ] || { echo "-E failed"; exit 1; }
+mkdir -p tmp.dir
+TMPDIR=tmp.dir ${mmcc2} -o tmp -e 'int main() { return 0; }' -lmmcc2-missing 2>/dev/null
+[ -z "$(ls tmp.dir)" ] || { echo "temporary files left after a failed link"; exit 1; }
+rm -rf tmp.dir
+${mmcc2} -S -o - tmp.c | grep -q 'note.GNU-stack' || { echo "-S: no .note.GNU-stack section"; exit 1; }
+echo "driver => OK"
+
 echo OK